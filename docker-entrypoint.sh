@@ -1,4 +1,9 @@
 #!/bin/sh
 set -e
 
-spangen "$@" | kcat -P -b "$KAFKA_BROKER" -t "$KAFKA_TOPIC" -p "${KAFKA_PARTITION:--1}"
+exec spangen \
+    ${KAFKA_BROKER:+--kafka-broker "$KAFKA_BROKER"} \
+    --kafka-topic "$KAFKA_TOPIC" \
+    ${KAFKA_PARTITION:+--kafka-partition "$KAFKA_PARTITION"} \
+    ${PROGRESS_INTERVAL:+--progress-interval "$PROGRESS_INTERVAL"} \
+    "$@"
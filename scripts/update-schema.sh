@@ -0,0 +1,32 @@
+#!/bin/sh
+# Vendors the snuba-spans schema verbatim from a release of getsentry/sentry-kafka-schemas and
+# records the release and commit it was taken from in schemas/SOURCE.
+#
+# Usage: scripts/update-schema.sh <tag>
+set -e
+
+REPO=getsentry/sentry-kafka-schemas
+SCHEMA=schemas/snuba-spans.v1.schema.json
+
+tag=${1:?usage: $0 <tag>}
+cd "$(dirname "$0")/.."
+
+# Annotated tags are listed twice, the peeled commit sorts last.
+commit=$(git ls-remote "https://github.com/$REPO" "refs/tags/$tag" "refs/tags/$tag^{}" |
+    sort -k 2 | tail -n 1 | cut -f 1)
+if [ -z "$commit" ]; then
+    echo "tag $tag not found in $REPO" >&2
+    exit 1
+fi
+
+curl -sSfL -o "$SCHEMA" "https://raw.githubusercontent.com/$REPO/$commit/$SCHEMA"
+
+cat > schemas/SOURCE <<SOURCE
+repository: https://github.com/$REPO
+path: $SCHEMA
+tag: $tag
+commit: $commit
+SOURCE
+
+echo "vendored $SCHEMA from $REPO $tag ($commit)"
+echo "run cargo test to check that generated spans match the new schema"
@@ -1,97 +1,605 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use argh::FromArgs;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use toml::Table;
+
+use crate::constants::{SENTRY_ENVIRONMENTS, SENTRY_RELEASES, SENTRY_TRANSACTIONS};
 
 pub const MAX_PROJECTS: u64 = 1000;
 
+/// The compression codecs that the Kafka producer is built with.
+pub const KAFKA_COMPRESSION_CODECS: &[&str] = &["none", "gzip", "snappy", "lz4"];
+
 /// A load generator for spans in traces.
-#[derive(Debug, FromArgs)]
-pub struct Config {
-    /// the number of spans to generate in total.
+///
+/// Options can also be set in a TOML scenario file passed with --scenario. Options on the command
+/// line take precedence over the scenario.
+#[derive(Debug, FromArgs, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Args {
+    /// a TOML file with options and additional settings for the run.
+    #[argh(option)]
+    #[serde(skip)]
+    pub scenario: Option<PathBuf>,
+
+    /// print the effective configuration as TOML and exit.
+    #[argh(switch)]
+    #[serde(skip)]
+    pub print_config: bool,
+
+    /// the number of spans to generate in total (required if --duration is not set).
     ///
     /// spangen will stop generating new traces after this number has been reached, but it will
     /// finish started traces and segments. The actual number of spans generated may therefore be
     /// higher than this option.
     #[argh(option)]
-    pub count: usize,
+    pub count: Option<usize>,
+
+    /// the time after which no new traces are started, for example 90s, 30m or 2h (required if
+    /// --count is not set).
+    ///
+    /// Combined with --count, the run stops at whichever limit is reached first. Traces that are
+    /// open at the deadline are finished.
+    #[argh(option)]
+    pub duration: Option<HumanDuration>,
+
+    /// log the rate, open traces and estimated time remaining at this interval, for example 10s
+    /// (defaults to no progress reporting).
+    #[argh(option)]
+    pub progress_interval: Option<HumanDuration>,
+
+    /// serve Prometheus metrics of the run at /metrics on this address, for example 0.0.0.0:9100.
+    #[argh(option)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// write a JSON summary of the run to this file at exit, with totals, per-organization and
+    /// per-project span counts, histograms of the generated shapes and the effective config.
+    #[argh(option)]
+    pub summary_json: Option<PathBuf>,
 
     /// the throughput of spans per second (defaults to no throttling).
     #[argh(option)]
     pub throughput: Option<u32>,
 
+    /// a throughput in spans per second that changes over time, replacing --throughput
+    /// (constant:RATE, ramp:FROM:TO:DURATION, steps:DURATION:R1,R2,...,
+    /// burst:BASE:PEAK:PERIOD:LENGTH, sine:MEAN:AMPLITUDE:PERIOD).
+    ///
+    /// Durations are written like 90s, 30m or 2h. A ramp stays at TO after DURATION, and steps
+    /// stay at the last rate. A burst runs at PEAK for LENGTH at the start of every PERIOD. A sine
+    /// with a period of 24h models diurnal traffic.
+    #[argh(option)]
+    pub throughput_schedule: Option<RateSchedule>,
+
+    /// space spans with exponentially distributed intervals, as in a Poisson process, instead of
+    /// evenly.
+    #[argh(switch)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub throughput_jitter: bool,
+
     /// the average number of spans per segment (randomized).
-    #[argh(option, default = "17")]
-    pub spans_per_segment: usize,
+    #[argh(option)]
+    pub spans_per_segment: Option<usize>,
 
     /// the standard deviation for randomizing the number of spans per segment.
-    #[argh(option, default = "17.0")]
-    pub spans_per_segment_stddev: f64,
+    #[argh(option)]
+    pub spans_per_segment_stddev: Option<f64>,
+
+    /// the distribution of the number of spans per segment, replacing --spans-per-segment and its
+    /// standard deviation (constant:VALUE, uniform:MIN:MAX, normal:MEAN:STDDEV,
+    /// lognormal:MEAN:STDDEV, pareto:SCALE:SHAPE, poisson:MEAN, exponential:MEAN, histogram:CSV).
+    ///
+    /// A histogram CSV file contains one value and its weight per line.
+    #[argh(option)]
+    pub spans_per_segment_distribution: Option<ValueDistribution>,
 
     /// the average number of segments per trace (randomized).
-    #[argh(option, default = "1")]
-    pub segments_per_trace: usize,
+    #[argh(option)]
+    pub segments_per_trace: Option<usize>,
 
     /// the standard deviation for randomizing the number of segments per trace.
-    #[argh(option, default = "1.0")]
-    pub segments_per_trace_stddev: f64,
+    #[argh(option)]
+    pub segments_per_trace_stddev: Option<f64>,
+
+    /// the distribution of the number of segments per trace, replacing --segments-per-trace and
+    /// its standard deviation (see --spans-per-segment-distribution).
+    #[argh(option)]
+    pub segments_per_trace_distribution: Option<ValueDistribution>,
 
     /// the order in which spans are written in a segment.
-    #[argh(option, default = "SpanOrder::Post")]
-    pub order: SpanOrder,
+    #[argh(option)]
+    pub order: Option<SpanOrder>,
 
     /// the maximum number of spans that will be generated in a single run.
     ///
     /// This is used to simulate a stream of spans that are generated in batches. SDKs do not
     /// typically generate spans one by one, but rather in batches. This parameter controls the
     /// maximum number of spans that will be generated in a single run.
-    #[argh(option, default = "100")]
-    #[allow(
-        dead_code,
-        reason = "TODO: Support batched emission with delays and receive time"
-    )]
-    pub batch_size: usize,
+    #[argh(option)]
+    pub batch_size: Option<usize>,
 
     /// the delay in milliseconds between consecutive batches of a segment.
     ///
     /// This is used to simulate an operating SDK that collects spans over time and flushes them in
     /// batches with a given delay.
-    #[argh(option, default = "2000")]
-    pub batch_delay_ms: u64,
+    #[argh(option)]
+    pub batch_delay_ms: Option<u64>,
 
     /// the standard deviation for the batch delay in milliseconds.
-    #[argh(option, default = "500")]
-    pub batch_delay_stddev: u64,
+    #[argh(option)]
+    pub batch_delay_stddev: Option<u64>,
+
+    /// the distribution of the batch delay in milliseconds, replacing --batch-delay-ms and its
+    /// standard deviation (see --spans-per-segment-distribution).
+    #[argh(option)]
+    pub batch_delay_distribution: Option<ValueDistribution>,
+
+    /// the average delay in milliseconds between flushing a batch and its receipt in ingestion.
+    #[argh(option)]
+    pub ingestion_lag_ms: Option<u64>,
+
+    /// the standard deviation for the ingestion lag in milliseconds.
+    #[argh(option)]
+    pub ingestion_lag_stddev: Option<u64>,
+
+    /// the distribution of the ingestion lag in milliseconds, replacing --ingestion-lag-ms and its
+    /// standard deviation (see --spans-per-segment-distribution).
+    #[argh(option)]
+    pub ingestion_lag_distribution: Option<ValueDistribution>,
 
     /// the number of concurrent traces that interleave on the stream.
-    #[argh(option, default = "1000")]
-    #[allow(dead_code, reason = "TODO: Implement concurrent traces")]
+    #[argh(option)]
+    pub concurrent_traces: Option<usize>,
+
+    /// the number of threads that generate spans in parallel.
+    ///
+    /// Each worker generates disjoint traces with its share of --count, --throughput and
+    /// --concurrent-traces. Messages of different workers interleave nondeterministically.
+    #[argh(option)]
+    pub workers: Option<usize>,
+
+    /// the target size of each serialized span in bytes.
+    ///
+    /// Spans are padded with filler data attributes until they reach this size. Spans that are
+    /// already larger are emitted as they are. Set to 0 to disable padding.
+    #[argh(option)]
+    pub payload_size: Option<usize>,
+
+    /// the standard deviation for randomizing the payload size in bytes.
+    #[argh(option)]
+    pub payload_size_stddev: Option<usize>,
+
+    /// the depth of the span tree within each segment.
+    #[argh(option)]
+    pub tree_depth: Option<usize>,
+
+    /// the distribution of the duration of root spans in milliseconds (defaults to uniform:1:2000,
+    /// see --spans-per-segment-distribution).
+    #[argh(option)]
+    pub root_duration_distribution: Option<ValueDistribution>,
+
+    /// the percentage of segments without an explicit root span (0..100).
+    ///
+    /// Only segments with more than one span can lose their root, since a segment without any
+    /// spans would not be emitted. The effective percentage is therefore lower when many segments
+    /// consist of a single span.
+    #[argh(option)]
+    pub segments_without_root: Option<u16>,
+
+    /// the format of the generated messages (snuba, otlp-json, otlp-proto, envelope).
+    ///
+    /// The snuba format writes one message per span. The OTLP formats write one
+    /// `ExportTraceServiceRequest` per batch, and the envelope format writes one Sentry envelope
+    /// with span items per batch. On stdout, protobuf messages are length-delimited.
+    #[argh(option)]
+    pub format: Option<OutputFormat>,
+
+    /// the file to write messages to (defaults to stdout).
+    #[argh(option)]
+    pub output: Option<PathBuf>,
+
+    /// the URL of the Relay that envelope DSNs point to.
+    #[argh(option)]
+    pub relay_url: Option<String>,
+
+    /// send envelopes to the envelope endpoint of --relay-url instead of writing them to --output.
+    #[argh(switch)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub http: bool,
+
+    /// the number of concurrent HTTP requests.
+    #[argh(option)]
+    pub http_concurrency: Option<usize>,
+
+    /// the maximum number of retries for rate limited or failed HTTP requests.
+    #[argh(option)]
+    pub http_max_retries: Option<u32>,
+
+    /// the Kafka bootstrap servers to produce to (defaults to writing to --output).
+    #[argh(option)]
+    pub kafka_broker: Option<String>,
+
+    /// the Kafka topic to produce to.
+    #[argh(option)]
+    pub kafka_topic: Option<String>,
+
+    /// the Kafka partition to produce to (defaults to partitioning by trace ID).
+    #[argh(option)]
+    pub kafka_partition: Option<i32>,
+
+    /// the compression codec for Kafka messages (none, gzip, snappy, lz4).
+    #[argh(option)]
+    pub kafka_compression: Option<String>,
+
+    /// the time in milliseconds the Kafka producer waits to fill a batch.
+    #[argh(option)]
+    pub kafka_linger_ms: Option<u64>,
+
+    /// the number of acknowledgements the Kafka producer requires (0, 1, all).
+    #[argh(option)]
+    pub kafka_acks: Option<String>,
+
+    /// re-emit spans from a snuba-spans JSONL file (optionally gzipped) instead of generating them.
+    ///
+    /// Trace and span IDs are rewritten and timestamps are shifted to --start-time, preserving the
+    /// tree structure and relative timing of the spans. Spans are emitted in file order at
+    /// --throughput until --count or --duration is reached or the file is exhausted.
+    #[argh(option)]
+    pub replay: Option<PathBuf>,
+
+    /// start over with new IDs when the replayed file is exhausted, until --count or --duration is
+    /// reached.
+    #[argh(switch)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub replay_loop: bool,
+
+    /// validate every message against the snuba-spans schema and fail if any are invalid.
+    #[argh(switch)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub validate: bool,
+
+    /// validate every line of a snuba-spans JSONL file against the schema and exit.
+    ///
+    /// Use - to read from stdin. No spans are generated in this mode.
+    #[argh(option)]
+    pub validate_file: Option<PathBuf>,
+
+    /// write a JSONL manifest with the segments of every generated trace to this file, or read it
+    /// with --verify.
+    ///
+    /// Every line lists a trace ID with the span ID, project ID and span count of its segments and
+    /// whether their root span was emitted.
+    #[argh(option)]
+    pub manifest: Option<PathBuf>,
+
+    /// compare a snuba-spans JSONL export from a downstream store against --manifest and exit.
+    ///
+    /// Use - to read from stdin. Reports missing, duplicated and extra spans and fails if there
+    /// are any. No spans are generated in this mode.
+    #[argh(option)]
+    pub verify: Option<PathBuf>,
+
+    /// the seed for all randomness, at most 9223372036854775807 (defaults to a random seed).
+    ///
+    /// Runs with the same seed, start time and options produce identical output. Seeds are
+    /// limited to the range of TOML integers, so that they can be stored in a scenario.
+    #[argh(option)]
+    pub seed: Option<u64>,
+
+    /// the unix timestamp in seconds at which the generated timeline starts (defaults to now).
+    #[argh(option)]
+    pub start_time: Option<i64>,
+
+    /// the number of organizations.
+    #[argh(option)]
+    pub orgs: Option<u64>,
+
+    /// the number of projects per organization.
+    #[argh(option)]
+    pub projects: Option<u64>,
+
+    /// the distribution of traffic across organizations (uniform, zipf:EXPONENT,
+    /// hot:COUNT:PERCENT, weights:W1,W2,...).
+    ///
+    /// Skewed distributions send more traffic to organizations with lower IDs. With hot, the first
+    /// COUNT organizations receive PERCENT of all traffic. With weights, organizations without an
+    /// explicit weight have a weight of 1.
+    #[argh(option)]
+    pub org_distribution: Option<TenantDistribution>,
+
+    /// the distribution of traffic across the projects of an organization (see
+    /// --org-distribution).
+    #[argh(option)]
+    pub project_distribution: Option<TenantDistribution>,
+}
+
+/// The effective configuration of a run.
+///
+/// This is the result of merging the options given on the command line into the scenario file.
+/// Field names in the scenario file are the same as the command line options.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The number of spans to generate in total.
+    pub count: Option<usize>,
+
+    /// The time after which no new traces are started.
+    pub duration: Option<HumanDuration>,
+
+    /// The interval at which progress is logged.
+    pub progress_interval: Option<HumanDuration>,
+
+    /// The address to serve Prometheus metrics on.
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// The file to write a JSON summary of the run to.
+    pub summary_json: Option<PathBuf>,
+
+    /// The throughput of spans per second (defaults to no throttling).
+    pub throughput: Option<u32>,
+
+    /// A throughput in spans per second that changes over time.
+    pub throughput_schedule: Option<RateSchedule>,
+
+    /// Space spans with exponentially distributed intervals instead of evenly.
+    pub throughput_jitter: bool,
+
+    /// The average number of spans per segment (randomized).
+    pub spans_per_segment: usize,
+
+    /// The standard deviation for randomizing the number of spans per segment.
+    pub spans_per_segment_stddev: f64,
+
+    /// The distribution of the number of spans per segment.
+    pub spans_per_segment_distribution: Option<ValueDistribution>,
+
+    /// The average number of segments per trace (randomized).
+    pub segments_per_trace: usize,
+
+    /// The standard deviation for randomizing the number of segments per trace.
+    pub segments_per_trace_stddev: f64,
+
+    /// The distribution of the number of segments per trace.
+    pub segments_per_trace_distribution: Option<ValueDistribution>,
+
+    /// The order in which spans are written in a segment.
+    pub order: SpanOrder,
+
+    /// The maximum number of spans that will be generated in a single run.
+    pub batch_size: usize,
+
+    /// The delay in milliseconds between consecutive batches of a segment.
+    pub batch_delay_ms: u64,
+
+    /// The standard deviation for the batch delay in milliseconds.
+    pub batch_delay_stddev: u64,
+
+    /// The distribution of the batch delay in milliseconds.
+    pub batch_delay_distribution: Option<ValueDistribution>,
+
+    /// The average delay in milliseconds between flushing a batch and its receipt in ingestion.
+    pub ingestion_lag_ms: u64,
+
+    /// The standard deviation for the ingestion lag in milliseconds.
+    pub ingestion_lag_stddev: u64,
+
+    /// The distribution of the ingestion lag in milliseconds.
+    pub ingestion_lag_distribution: Option<ValueDistribution>,
+
+    /// The number of concurrent traces that interleave on the stream.
     pub concurrent_traces: usize,
 
-    /// the size of the payload in bytes.
-    #[argh(option, default = "14400")]
-    #[allow(dead_code, reason = "TODO: Support configuring data size")]
+    /// The number of threads that generate spans in parallel.
+    pub workers: usize,
+
+    /// The target size of each serialized span in bytes.
     pub payload_size: usize,
 
-    /// the depth of the span tree within each segment.
-    #[argh(option, default = "3")]
+    /// The standard deviation for randomizing the payload size in bytes.
+    pub payload_size_stddev: usize,
+
+    /// The depth of the span tree within each segment.
     pub tree_depth: usize,
 
-    /// the percentage of segments without an explicit root span (0..100)
-    #[argh(option, default = "0")]
-    #[allow(dead_code, reason = "TODO: Support configuring data size")]
+    /// The distribution of the duration of root spans in milliseconds.
+    pub root_duration_distribution: Option<ValueDistribution>,
+
+    /// The percentage of segments without an explicit root span (0..100), among segments with
+    /// more than one span.
     pub segments_without_root: u16,
 
-    /// the number of organizations.
-    #[argh(option, default = "1000")]
+    /// The format of the generated messages (snuba, otlp-json, otlp-proto, envelope).
+    pub format: OutputFormat,
+
+    /// The file to write messages to (defaults to stdout).
+    pub output: Option<PathBuf>,
+
+    /// The URL of the Relay that envelope DSNs point to.
+    pub relay_url: String,
+
+    /// Send envelopes to the envelope endpoint of --relay-url instead of writing them to --output.
+    pub http: bool,
+
+    /// The number of concurrent HTTP requests.
+    pub http_concurrency: usize,
+
+    /// The maximum number of retries for rate limited or failed HTTP requests.
+    pub http_max_retries: u32,
+
+    /// The Kafka bootstrap servers to produce to (defaults to writing to --output).
+    pub kafka_broker: Option<String>,
+
+    /// The Kafka topic to produce to.
+    pub kafka_topic: String,
+
+    /// The Kafka partition to produce to (defaults to partitioning by trace ID).
+    pub kafka_partition: Option<i32>,
+
+    /// The compression codec for Kafka messages (none, gzip, snappy, lz4).
+    pub kafka_compression: String,
+
+    /// The time in milliseconds the Kafka producer waits to fill a batch.
+    pub kafka_linger_ms: u64,
+
+    /// The number of acknowledgements the Kafka producer requires (0, 1, all).
+    pub kafka_acks: String,
+
+    /// A snuba-spans JSONL file to re-emit spans from instead of generating them.
+    pub replay: Option<PathBuf>,
+
+    /// Start over with new IDs when the replayed file is exhausted.
+    pub replay_loop: bool,
+
+    /// Validate every message against the snuba-spans schema.
+    pub validate: bool,
+
+    /// A snuba-spans JSONL file to validate instead of generating spans.
+    pub validate_file: Option<PathBuf>,
+
+    /// The file to write the manifest of generated traces to, or to read it from with `verify`.
+    pub manifest: Option<PathBuf>,
+
+    /// A snuba-spans JSONL export to compare against the manifest instead of generating spans.
+    pub verify: Option<PathBuf>,
+
+    /// The seed for all randomness (defaults to a random seed).
+    pub seed: u64,
+
+    /// The unix timestamp in seconds at which the generated timeline starts (defaults to now).
+    pub start_time: i64,
+
+    /// The number of organizations.
     pub orgs: u64,
 
-    /// the number of projects per organization.
-    #[argh(option, default = "10")]
+    /// The number of projects per organization.
     pub projects: u64,
+
+    /// The distribution of traffic across organizations.
+    pub org_distribution: TenantDistribution,
+
+    /// The distribution of traffic across the projects of an organization.
+    pub project_distribution: TenantDistribution,
+
+    /// Values that generated spans pick from.
+    pub dictionaries: Dictionaries,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            count: None,
+            duration: None,
+            progress_interval: None,
+            metrics_addr: None,
+            summary_json: None,
+            throughput: None,
+            throughput_schedule: None,
+            throughput_jitter: false,
+            spans_per_segment: 17,
+            spans_per_segment_stddev: 17.0,
+            spans_per_segment_distribution: None,
+            segments_per_trace: 1,
+            segments_per_trace_stddev: 1.0,
+            segments_per_trace_distribution: None,
+            order: SpanOrder::Post,
+            batch_size: 100,
+            batch_delay_ms: 2000,
+            batch_delay_stddev: 500,
+            batch_delay_distribution: None,
+            ingestion_lag_ms: 100,
+            ingestion_lag_stddev: 50,
+            ingestion_lag_distribution: None,
+            concurrent_traces: 1000,
+            workers: 1,
+            payload_size: 14400,
+            payload_size_stddev: 0,
+            tree_depth: 3,
+            root_duration_distribution: None,
+            segments_without_root: 0,
+            format: OutputFormat::Snuba,
+            output: None,
+            relay_url: String::from("http://localhost:3000"),
+            http: false,
+            http_concurrency: 8,
+            http_max_retries: 5,
+            kafka_broker: None,
+            kafka_topic: String::from("snuba-spans"),
+            kafka_partition: None,
+            kafka_compression: String::from("none"),
+            kafka_linger_ms: 5,
+            kafka_acks: String::from("all"),
+            replay: None,
+            replay_loop: false,
+            validate: false,
+            validate_file: None,
+            manifest: None,
+            verify: None,
+            // TOML only supports signed integers, so the default seed must fit into an i64.
+            seed: rand::random_range(0..=i64::MAX as u64),
+            start_time: OffsetDateTime::now_utc().unix_timestamp(),
+            orgs: 1000,
+            projects: 10,
+            org_distribution: TenantDistribution::Uniform,
+            project_distribution: TenantDistribution::Uniform,
+            dictionaries: Dictionaries::default(),
+        }
+    }
 }
 
 impl Config {
+    /// Loads the scenario file given in `args` and applies the command line options on top.
+    pub fn load(args: &Args) -> Result<Self> {
+        let mut table = match args.scenario {
+            Some(ref path) => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read scenario {}", path.display()))?;
+                toml::from_str::<Table>(&contents)
+                    .with_context(|| format!("failed to parse scenario {}", path.display()))?
+            }
+            None => Table::new(),
+        };
+
+        // TOML only supports signed integers, see the default seed.
+        if args.seed.is_some_and(|seed| seed > i64::MAX as u64) {
+            anyhow::bail!("seed must be at most {}", i64::MAX);
+        }
+
+        let overrides = Table::try_from(args).context("invalid command line options")?;
+
+        // Throughput options are mutually exclusive, so either one on the command line replaces
+        // both of them in the scenario.
+        if overrides.contains_key("throughput") || overrides.contains_key("throughput-schedule") {
+            table.remove("throughput");
+            table.remove("throughput-schedule");
+        }
+
+        table.extend(overrides);
+
+        // A replay ends when the file is exhausted, unless it is looped.
+        let finite_replay = table.contains_key("replay")
+            && table.get("replay-loop").and_then(toml::Value::as_bool) != Some(true);
+
+        if !table.contains_key("count")
+            && !table.contains_key("duration")
+            && !table.contains_key("validate-file")
+            && !table.contains_key("verify")
+            && !finite_replay
+        {
+            anyhow::bail!("count or duration must be set on the command line or in the scenario");
+        }
+
+        let config = table.try_into().context("invalid scenario")?;
+
+        Ok(config)
+    }
+
     pub fn validate(&mut self) -> Result<()> {
         if self.orgs == 0 {
             log::error!("invalid number of orgs, using default value of 1");
@@ -116,16 +624,158 @@ impl Config {
             self.tree_depth = 1;
         }
 
+        if self.batch_size == 0 {
+            log::error!("invalid batch size, using default value of 1");
+            self.batch_size = 1;
+        }
+
+        if self.concurrent_traces == 0 {
+            log::error!("invalid number of concurrent traces, using default value of 1");
+            self.concurrent_traces = 1;
+        }
+
+        if self.workers == 0 {
+            log::error!("invalid number of workers, using default value of 1");
+            self.workers = 1;
+        }
+
+        if self.throughput == Some(0) {
+            anyhow::bail!("throughput must be greater than 0");
+        }
+
+        if self
+            .throughput_schedule
+            .as_ref()
+            .is_some_and(|schedule| schedule.min_rate() <= 0.0)
+        {
+            anyhow::bail!("throughput-schedule must stay above 0 at all times");
+        }
+
+        let workers = self.workers;
+        let peak_rate = self.rate_schedule().map(|schedule| schedule.max_rate());
+        if let Some(peak_rate) = peak_rate.filter(|&rate| rate < workers as f64) {
+            let workers = (peak_rate as usize).max(1);
+            log::error!("throughput too low for all workers, using {workers} workers");
+            self.workers = workers;
+        }
+
+        if self.concurrent_traces < self.workers {
+            log::error!(
+                "too few concurrent traces for all workers, using {} workers",
+                self.concurrent_traces
+            );
+            self.workers = self.concurrent_traces;
+        }
+
+        if self.replay.is_some() && self.workers > 1 {
+            anyhow::bail!("replay does not support multiple workers");
+        }
+
+        if self.verify.is_some() && self.manifest.is_none() {
+            anyhow::bail!("verify requires manifest");
+        }
+
+        if self.replay.is_some() && self.manifest.is_some() {
+            anyhow::bail!("manifest is not supported with replay");
+        }
+
+        if self.throughput.is_some() && self.throughput_schedule.is_some() {
+            anyhow::bail!("throughput and throughput-schedule cannot be combined");
+        }
+
+        if self
+            .progress_interval
+            .is_some_and(|interval| interval.0.is_zero())
+        {
+            anyhow::bail!("progress-interval must not be zero");
+        }
+
         if self.segments_without_root > 100 {
             anyhow::bail!("segments-without-root must be between 0 and 100");
         }
 
+        if self.http && self.format != OutputFormat::Envelope {
+            anyhow::bail!("http requires the envelope format");
+        }
+
+        if self.http && self.kafka_broker.is_some() {
+            anyhow::bail!("http and kafka-broker cannot be combined");
+        }
+
+        if !KAFKA_COMPRESSION_CODECS.contains(&self.kafka_compression.as_str()) {
+            anyhow::bail!(
+                "unsupported kafka-compression {}, expected one of {}",
+                self.kafka_compression,
+                KAFKA_COMPRESSION_CODECS.join(", ")
+            );
+        }
+
+        if self.replay.is_some() && self.format != OutputFormat::Snuba {
+            anyhow::bail!("replay requires the snuba format");
+        }
+
+        if self.validate && self.format != OutputFormat::Snuba {
+            anyhow::bail!("validate requires the snuba format");
+        }
+
+        if self.http_concurrency == 0 {
+            log::error!("invalid HTTP concurrency, using default value of 1");
+            self.http_concurrency = 1;
+        }
+
+        let dictionaries = &self.dictionaries;
+        if dictionaries.releases.is_empty()
+            || dictionaries.environments.is_empty()
+            || dictionaries.transactions.is_empty()
+        {
+            anyhow::bail!("dictionaries must not be empty");
+        }
+
+        if OffsetDateTime::from_unix_timestamp(self.start_time).is_err() {
+            anyhow::bail!("start-time is not a valid unix timestamp");
+        }
+
         Ok(())
     }
+
+    /// Returns the schedule of the throughput, or `None` if throughput is not limited.
+    pub fn rate_schedule(&self) -> Option<RateSchedule> {
+        match self.throughput_schedule {
+            Some(ref schedule) => Some(schedule.clone()),
+            None => self
+                .throughput
+                .map(|rate| RateSchedule::Constant { rate: rate as f64 }),
+        }
+    }
+}
+
+/// Lists of values that generated spans pick from.
+///
+/// These can only be set in the scenario file and default to a sample of real values.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dictionaries {
+    /// The releases of projects.
+    pub releases: Vec<String>,
+    /// The environments of projects.
+    pub environments: Vec<String>,
+    /// The transaction names of segments.
+    pub transactions: Vec<String>,
+}
+
+impl Default for Dictionaries {
+    fn default() -> Self {
+        Self {
+            releases: SENTRY_RELEASES.iter().map(|&s| s.to_owned()).collect(),
+            environments: SENTRY_ENVIRONMENTS.iter().map(|&s| s.to_owned()).collect(),
+            transactions: SENTRY_TRANSACTIONS.iter().map(|&s| s.to_owned()).collect(),
+        }
+    }
 }
 
 /// The order in which spans are written in a segment.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum SpanOrder {
     /// Parents are written after their children.
     Post,
@@ -147,3 +797,832 @@ impl FromStr for SpanOrder {
         }
     }
 }
+
+/// The format of the generated messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// One `snuba-spans` JSON message per span.
+    Snuba,
+    /// One OTLP/JSON `ExportTraceServiceRequest` per batch.
+    OtlpJson,
+    /// One OTLP protobuf `ExportTraceServiceRequest` per batch.
+    OtlpProto,
+    /// One Sentry envelope with span items per batch.
+    Envelope,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "snuba" => Ok(OutputFormat::Snuba),
+            "otlp-json" => Ok(OutputFormat::OtlpJson),
+            "otlp-proto" => Ok(OutputFormat::OtlpProto),
+            "envelope" => Ok(OutputFormat::Envelope),
+            _ => anyhow::bail!("invalid output format: {}", s),
+        }
+    }
+}
+
+/// A duration written like `90s`, `30m` or `1h 30m`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let duration =
+            humantime::parse_duration(s).with_context(|| format!("invalid duration: {}", s))?;
+        Ok(HumanDuration(duration))
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.0))
+    }
+}
+
+impl TryFrom<String> for HumanDuration {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<HumanDuration> for String {
+    fn from(value: HumanDuration) -> Self {
+        value.to_string()
+    }
+}
+
+/// A throughput in spans per second that changes over the course of a run.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RateSchedule {
+    /// Always the same rate.
+    Constant { rate: f64 },
+    /// Changes linearly from `from` to `to` over `duration` and then stays at `to`.
+    Ramp {
+        from: f64,
+        to: f64,
+        duration: Duration,
+    },
+    /// Every rate lasts for `duration`, and the last rate stays in effect.
+    Steps { duration: Duration, rates: Vec<f64> },
+    /// Runs at `peak` for `length` at the start of every `period`, and at `base` otherwise.
+    Burst {
+        base: f64,
+        peak: f64,
+        period: Duration,
+        length: Duration,
+    },
+    /// Oscillates around `mean` by `amplitude`, rising first.
+    Sine {
+        mean: f64,
+        amplitude: f64,
+        period: Duration,
+    },
+}
+
+impl RateSchedule {
+    /// Returns the rate in spans per second at `elapsed` since the start of the run.
+    pub fn rate(&self, elapsed: Duration) -> f64 {
+        let elapsed = elapsed.as_secs_f64();
+
+        match *self {
+            RateSchedule::Constant { rate } => rate,
+            RateSchedule::Ramp { from, to, duration } => {
+                let progress = (elapsed / duration.as_secs_f64()).min(1.0);
+                from + (to - from) * progress
+            }
+            RateSchedule::Steps {
+                duration,
+                ref rates,
+            } => {
+                let index = (elapsed / duration.as_secs_f64()) as usize;
+                rates[index.min(rates.len() - 1)]
+            }
+            RateSchedule::Burst {
+                base,
+                peak,
+                period,
+                length,
+            } => {
+                if elapsed % period.as_secs_f64() < length.as_secs_f64() {
+                    peak
+                } else {
+                    base
+                }
+            }
+            RateSchedule::Sine {
+                mean,
+                amplitude,
+                period,
+            } => {
+                let phase = elapsed / period.as_secs_f64() * std::f64::consts::TAU;
+                (mean + amplitude * phase.sin()).max(0.0)
+            }
+        }
+    }
+
+    /// Returns the lowest rate that the schedule runs at.
+    pub fn min_rate(&self) -> f64 {
+        match *self {
+            RateSchedule::Constant { rate } => rate,
+            RateSchedule::Ramp { from, to, .. } => from.min(to),
+            RateSchedule::Steps { ref rates, .. } => rates.iter().copied().fold(f64::MAX, f64::min),
+            RateSchedule::Burst { base, peak, .. } => base.min(peak),
+            RateSchedule::Sine {
+                mean, amplitude, ..
+            } => mean - amplitude,
+        }
+    }
+
+    /// Returns the highest rate that the schedule runs at.
+    pub fn max_rate(&self) -> f64 {
+        match *self {
+            RateSchedule::Constant { rate } => rate,
+            RateSchedule::Ramp { from, to, .. } => from.max(to),
+            RateSchedule::Steps { ref rates, .. } => rates.iter().copied().fold(0.0, f64::max),
+            RateSchedule::Burst { base, peak, .. } => base.max(peak),
+            RateSchedule::Sine {
+                mean, amplitude, ..
+            } => mean + amplitude,
+        }
+    }
+
+    /// Returns the same schedule with all rates multiplied by `factor`.
+    pub fn scaled(&self, factor: f64) -> Self {
+        let mut schedule = self.clone();
+
+        match schedule {
+            RateSchedule::Constant { ref mut rate } => *rate *= factor,
+            RateSchedule::Ramp {
+                ref mut from,
+                ref mut to,
+                ..
+            } => {
+                *from *= factor;
+                *to *= factor;
+            }
+            RateSchedule::Steps { ref mut rates, .. } => {
+                rates.iter_mut().for_each(|rate| *rate *= factor);
+            }
+            RateSchedule::Burst {
+                ref mut base,
+                ref mut peak,
+                ..
+            } => {
+                *base *= factor;
+                *peak *= factor;
+            }
+            RateSchedule::Sine {
+                ref mut mean,
+                ref mut amplitude,
+                ..
+            } => {
+                *mean *= factor;
+                *amplitude *= factor;
+            }
+        }
+
+        schedule
+    }
+}
+
+impl FromStr for RateSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid throughput schedule: {}", s);
+        let rate = |param: &str| {
+            param
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| rate.is_finite() && *rate >= 0.0)
+                .ok_or_else(invalid)
+        };
+        let duration = |param: &str| {
+            humantime::parse_duration(param.trim())
+                .ok()
+                .filter(|duration| !duration.is_zero())
+                .ok_or_else(invalid)
+        };
+
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let params = params.split(':').collect::<Vec<_>>();
+
+        Ok(match (name.to_lowercase().as_str(), params.as_slice()) {
+            ("constant", [value]) => RateSchedule::Constant { rate: rate(value)? },
+            ("ramp", [from, to, length]) => RateSchedule::Ramp {
+                from: rate(from)?,
+                to: rate(to)?,
+                duration: duration(length)?,
+            },
+            ("steps", [length, rates]) => RateSchedule::Steps {
+                duration: duration(length)?,
+                rates: rates.split(',').map(rate).collect::<Result<_>>()?,
+            },
+            ("burst", [base, peak, period, length]) => {
+                let (period, length) = (duration(period)?, duration(length)?);
+                if length > period {
+                    anyhow::bail!("burst length must not be longer than the period");
+                }
+                RateSchedule::Burst {
+                    base: rate(base)?,
+                    peak: rate(peak)?,
+                    period,
+                    length,
+                }
+            }
+            ("sine", [mean, amplitude, period]) => RateSchedule::Sine {
+                mean: rate(mean)?,
+                amplitude: rate(amplitude)?,
+                period: duration(period)?,
+            },
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl fmt::Display for RateSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use humantime::format_duration;
+
+        match self {
+            RateSchedule::Constant { rate } => write!(f, "constant:{rate}"),
+            RateSchedule::Ramp { from, to, duration } => {
+                write!(f, "ramp:{from}:{to}:{}", format_duration(*duration))
+            }
+            RateSchedule::Steps { duration, rates } => {
+                write!(f, "steps:{}:", format_duration(*duration))?;
+                for (i, rate) in rates.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{rate}")?;
+                }
+                Ok(())
+            }
+            RateSchedule::Burst {
+                base,
+                peak,
+                period,
+                length,
+            } => write!(
+                f,
+                "burst:{base}:{peak}:{}:{}",
+                format_duration(*period),
+                format_duration(*length)
+            ),
+            RateSchedule::Sine {
+                mean,
+                amplitude,
+                period,
+            } => write!(f, "sine:{mean}:{amplitude}:{}", format_duration(*period)),
+        }
+    }
+}
+
+impl TryFrom<String> for RateSchedule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<RateSchedule> for String {
+    fn from(value: RateSchedule) -> Self {
+        value.to_string()
+    }
+}
+
+/// The distribution of traffic across organizations or projects.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TenantDistribution {
+    /// All tenants receive the same share of traffic.
+    Uniform,
+    /// The share of the tenant with rank `k` is proportional to `1 / k^exponent`.
+    Zipf { exponent: f64 },
+    /// The first `count` tenants receive `percent` percent of traffic.
+    Hot { count: u64, percent: f64 },
+    /// Explicit weights for the first tenants. All other tenants have a weight of 1.
+    Weights(Vec<f64>),
+}
+
+impl FromStr for TenantDistribution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+
+        let distribution = match (name.to_lowercase().as_str(), params) {
+            ("uniform", "") => TenantDistribution::Uniform,
+            ("zipf", exponent) => TenantDistribution::Zipf {
+                exponent: exponent.parse()?,
+            },
+            ("hot", params) => {
+                let (count, percent) = params
+                    .split_once(':')
+                    .with_context(|| format!("invalid tenant distribution: {}", s))?;
+                TenantDistribution::Hot {
+                    count: count.parse()?,
+                    percent: percent.parse()?,
+                }
+            }
+            ("weights", weights) => TenantDistribution::Weights(
+                weights
+                    .split(',')
+                    .map(|w| w.trim().parse())
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => anyhow::bail!("invalid tenant distribution: {}", s),
+        };
+
+        match distribution {
+            TenantDistribution::Zipf { exponent } if exponent.is_nan() || exponent < 0.0 => {
+                anyhow::bail!("zipf exponent must not be negative")
+            }
+            TenantDistribution::Hot { percent, .. } if !(0.0..=100.0).contains(&percent) => {
+                anyhow::bail!("hot percentage must be between 0 and 100")
+            }
+            TenantDistribution::Weights(ref weights)
+                if weights.iter().any(|w| !w.is_finite() || *w < 0.0) =>
+            {
+                anyhow::bail!("weights must not be negative")
+            }
+            _ => Ok(distribution),
+        }
+    }
+}
+
+impl fmt::Display for TenantDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TenantDistribution::Uniform => write!(f, "uniform"),
+            TenantDistribution::Zipf { exponent } => write!(f, "zipf:{exponent}"),
+            TenantDistribution::Hot { count, percent } => write!(f, "hot:{count}:{percent}"),
+            TenantDistribution::Weights(weights) => {
+                write!(f, "weights:")?;
+                for (i, weight) in weights.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{weight}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for TenantDistribution {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<TenantDistribution> for String {
+    fn from(value: TenantDistribution) -> Self {
+        value.to_string()
+    }
+}
+
+/// The distribution of a randomized count, duration or delay.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ValueDistribution {
+    /// Always the same value.
+    Constant { value: f64 },
+    /// Uniformly distributed between `min` and `max`, inclusive.
+    Uniform { min: f64, max: f64 },
+    /// A normal distribution.
+    Normal { mean: f64, stddev: f64 },
+    /// A log-normal distribution with the given mean and standard deviation.
+    LogNormal { mean: f64, stddev: f64 },
+    /// A Pareto distribution with a minimum value of `scale`.
+    Pareto { scale: f64, shape: f64 },
+    /// A Poisson distribution.
+    Poisson { mean: f64 },
+    /// An exponential distribution.
+    Exponential { mean: f64 },
+    /// An empirical distribution loaded from a CSV file with values and their weights.
+    Histogram { path: PathBuf },
+}
+
+impl FromStr for ValueDistribution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+
+        Ok(match name.to_lowercase().as_str() {
+            "constant" => {
+                let [value] = parse_params(s, params)?;
+                ValueDistribution::Constant { value }
+            }
+            "uniform" => {
+                let [min, max] = parse_params(s, params)?;
+                if min > max {
+                    anyhow::bail!("uniform minimum must not be greater than the maximum");
+                }
+                ValueDistribution::Uniform { min, max }
+            }
+            "normal" => {
+                let [mean, stddev] = parse_params(s, params)?;
+                ValueDistribution::Normal { mean, stddev }
+            }
+            "lognormal" => {
+                let [mean, stddev] = parse_params(s, params)?;
+                ValueDistribution::LogNormal { mean, stddev }
+            }
+            "pareto" => {
+                let [scale, shape] = parse_params(s, params)?;
+                ValueDistribution::Pareto { scale, shape }
+            }
+            "poisson" => {
+                let [mean] = parse_params(s, params)?;
+                ValueDistribution::Poisson { mean }
+            }
+            "exponential" => {
+                let [mean] = parse_params(s, params)?;
+                ValueDistribution::Exponential { mean }
+            }
+            "histogram" if !params.is_empty() => ValueDistribution::Histogram {
+                path: PathBuf::from(params),
+            },
+            _ => anyhow::bail!("invalid distribution: {}", s),
+        })
+    }
+}
+
+/// Parses exactly `N` finite numbers separated by colons.
+fn parse_params<const N: usize>(spec: &str, params: &str) -> Result<[f64; N]> {
+    let values = params
+        .split(':')
+        .map(|param| param.parse::<f64>().ok().filter(|value| value.is_finite()))
+        .collect::<Option<Vec<_>>>()
+        .with_context(|| format!("invalid distribution: {}", spec))?;
+
+    values
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("invalid distribution: {}", spec))
+}
+
+impl fmt::Display for ValueDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueDistribution::Constant { value } => write!(f, "constant:{value}"),
+            ValueDistribution::Uniform { min, max } => write!(f, "uniform:{min}:{max}"),
+            ValueDistribution::Normal { mean, stddev } => write!(f, "normal:{mean}:{stddev}"),
+            ValueDistribution::LogNormal { mean, stddev } => {
+                write!(f, "lognormal:{mean}:{stddev}")
+            }
+            ValueDistribution::Pareto { scale, shape } => write!(f, "pareto:{scale}:{shape}"),
+            ValueDistribution::Poisson { mean } => write!(f, "poisson:{mean}"),
+            ValueDistribution::Exponential { mean } => write!(f, "exponential:{mean}"),
+            ValueDistribution::Histogram { path } => write!(f, "histogram:{}", path.display()),
+        }
+    }
+}
+
+impl TryFrom<String> for ValueDistribution {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<ValueDistribution> for String {
+    fn from(value: ValueDistribution) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+    use std::path::Path;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::from_args(&["spangen"], args).unwrap()
+    }
+
+    /// A scenario in a temporary file that is deleted when dropped.
+    struct Scenario(PathBuf);
+
+    impl Deref for Scenario {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for Scenario {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Writes a scenario to a temporary file that is unique to the calling test.
+    fn scenario(name: &str, contents: &str) -> Scenario {
+        let path = std::env::temp_dir().join(format!("spangen-{}-{name}.toml", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        Scenario(path)
+    }
+
+    fn load(scenario: &Path, args: &[&str]) -> Result<Config> {
+        let mut args = self::args(args);
+        args.scenario = Some(scenario.to_owned());
+        Config::load(&args)
+    }
+
+    #[test]
+    fn test_load_requires_limit() {
+        assert!(Config::load(&args(&[])).is_err());
+        assert!(Config::load(&args(&["--validate-file", "-"])).is_ok());
+    }
+
+    #[test]
+    fn test_load_replay_without_limit() {
+        let config = Config::load(&args(&["--replay", "dump.jsonl"])).unwrap();
+        assert_eq!(config.count, None);
+
+        assert!(Config::load(&args(&["--replay", "dump.jsonl", "--replay-loop"])).is_err());
+    }
+
+    #[test]
+    fn test_load_command_line_overrides_scenario() {
+        let path = scenario("overrides", "count = 10\norgs = 3\n");
+
+        let config = load(&path, &["--count", "20"]).unwrap();
+        assert_eq!(config.count, Some(20));
+        assert_eq!(config.orgs, 3);
+
+        let config = load(&path, &[]).unwrap();
+        assert_eq!(config.count, Some(10));
+        assert_eq!(config.projects, Config::default().projects);
+    }
+
+    #[test]
+    fn test_load_nested_tables() {
+        let path = scenario(
+            "nested",
+            "duration = \"90s\"\n[dictionaries]\nreleases = [\"backend@1.0.0\"]\n",
+        );
+
+        let config = load(&path, &[]).unwrap();
+        assert_eq!(config.duration.unwrap().0, Duration::from_secs(90));
+        assert_eq!(config.dictionaries.releases, ["backend@1.0.0"]);
+        assert_eq!(
+            config.dictionaries.environments,
+            Dictionaries::default().environments
+        );
+    }
+
+    #[test]
+    fn test_load_throughput_replaces_scenario() {
+        let path = scenario("throughput", "count = 10\nthroughput = 5\n");
+
+        let mut config = load(&path, &["--throughput-schedule", "constant:3"]).unwrap();
+        assert_eq!(config.throughput, None);
+        assert!(config.throughput_schedule.is_some());
+        config.validate().unwrap();
+
+        let path = scenario(
+            "schedule",
+            "count = 10\nthroughput-schedule = \"constant:3\"\n",
+        );
+        let mut config = load(&path, &["--throughput", "5"]).unwrap();
+        assert_eq!(config.throughput, Some(5));
+        assert!(config.throughput_schedule.is_none());
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_throughput() {
+        let mut config = Config::load(&args(&["--count", "1", "--throughput", "0"])).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::load(&args(&[
+            "--count",
+            "1",
+            "--throughput",
+            "2",
+            "--workers",
+            "4",
+        ]))
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.workers, 2);
+    }
+
+    #[test]
+    fn test_validate_throughput_schedule() {
+        for spec in [
+            "constant:0",
+            "ramp:0:100:1m",
+            "ramp:100:0:1m",
+            "steps:1m:0,0",
+            "steps:1m:100,0",
+            "burst:0:100:1m:10s",
+            "sine:100:100:1h",
+        ] {
+            let mut config =
+                Config::load(&args(&["--count", "1", "--throughput-schedule", spec])).unwrap();
+            assert!(config.validate().is_err(), "{spec}");
+        }
+
+        let mut config = Config::load(&args(&[
+            "--count",
+            "1",
+            "--throughput-schedule",
+            "ramp:0.5:2:1m",
+            "--workers",
+            "4",
+        ]))
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.workers, 2);
+    }
+
+    #[test]
+    fn test_validate_kafka_compression() {
+        let mut config =
+            Config::load(&args(&["--count", "1", "--kafka-compression", "zstd"])).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config =
+            Config::load(&args(&["--count", "1", "--kafka-compression", "lz4"])).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_load_seed_range() {
+        let max = i64::MAX.to_string();
+        let config = Config::load(&args(&["--count", "1", "--seed", &max])).unwrap();
+        assert_eq!(config.seed, i64::MAX as u64);
+
+        let too_large = u64::MAX.to_string();
+        assert!(Config::load(&args(&["--count", "1", "--seed", &too_large])).is_err());
+    }
+
+    #[test]
+    fn test_tenant_distribution_round_trip() {
+        for spec in ["uniform", "zipf:1.2", "hot:10:80", "weights:5,0.5,2"] {
+            let distribution: TenantDistribution = spec.parse().unwrap();
+            assert_eq!(distribution.to_string(), spec);
+            assert_eq!(
+                distribution
+                    .to_string()
+                    .parse::<TenantDistribution>()
+                    .unwrap(),
+                distribution
+            );
+        }
+
+        assert_eq!(
+            "ZIPF:1".parse::<TenantDistribution>().unwrap(),
+            TenantDistribution::Zipf { exponent: 1.0 }
+        );
+    }
+
+    #[test]
+    fn test_tenant_distribution_invalid() {
+        for spec in [
+            "",
+            "uniform:1",
+            "zipf",
+            "zipf:-1",
+            "zipf:NaN",
+            "hot:10",
+            "hot:10:101",
+            "hot:-1:50",
+            "weights:",
+            "weights:1,-2",
+            "weights:1,inf",
+            "pareto:1:2",
+        ] {
+            assert!(spec.parse::<TenantDistribution>().is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_value_distribution_round_trip() {
+        for spec in [
+            "constant:5",
+            "uniform:1:10",
+            "normal:17:17.5",
+            "lognormal:100:50",
+            "pareto:1000:1.5",
+            "poisson:3",
+            "exponential:250",
+            "histogram:spans.csv",
+            "histogram:/data/spans:v2.csv",
+        ] {
+            let distribution: ValueDistribution = spec.parse().unwrap();
+            assert_eq!(distribution.to_string(), spec);
+            assert_eq!(
+                distribution
+                    .to_string()
+                    .parse::<ValueDistribution>()
+                    .unwrap(),
+                distribution
+            );
+        }
+
+        assert_eq!(
+            "Normal:-1:0".parse::<ValueDistribution>().unwrap(),
+            ValueDistribution::Normal {
+                mean: -1.0,
+                stddev: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_value_distribution_invalid() {
+        for spec in [
+            "",
+            "constant",
+            "constant:",
+            "constant:1:2",
+            "uniform:10:1",
+            "normal:1",
+            "normal:inf:1",
+            "normal:NaN:1",
+            "pareto:a:b",
+            "histogram",
+            "histogram:",
+            "gamma:1:2",
+        ] {
+            assert!(spec.parse::<ValueDistribution>().is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_rate_schedule_round_trip() {
+        for spec in [
+            "constant:3",
+            "constant:0.5",
+            "ramp:0:100:1m",
+            "steps:30s:1,2,3",
+            "burst:10:100:1m:10s",
+            "burst:10:100:1h 30m:1h 30m",
+            "sine:100:50:1day",
+        ] {
+            let schedule: RateSchedule = spec.parse().unwrap();
+            assert_eq!(schedule.to_string(), spec);
+            assert_eq!(
+                schedule.to_string().parse::<RateSchedule>().unwrap(),
+                schedule
+            );
+        }
+
+        assert_eq!(
+            "Ramp: 1 : 2 : 90s".parse::<RateSchedule>().unwrap(),
+            RateSchedule::Ramp {
+                from: 1.0,
+                to: 2.0,
+                duration: Duration::from_secs(90),
+            }
+        );
+    }
+
+    #[test]
+    fn test_rate_schedule_invalid() {
+        for spec in [
+            "",
+            "constant",
+            "constant:-1",
+            "constant:inf",
+            "constant:NaN",
+            "ramp:0:100",
+            "ramp:0:100:0s",
+            "steps:30s:",
+            "steps:30s:1,,2",
+            "steps:0s:1",
+            "burst:10:100:10s:1m",
+            "burst:10:100:1m:0s",
+            "sine:100:50:1x",
+            "square:1:2:1m",
+        ] {
+            assert!(spec.parse::<RateSchedule>().is_err(), "{spec}");
+        }
+    }
+}
@@ -104,3 +104,14 @@ pub static SENTRY_SDKS: [&str; 3] = ["sentry.javascript.browser", "sentry.python
 pub static SENTRY_PLATFORMS: [&str; 4] = ["javascript", "python", "rust", "other"];
 
 pub static THREAD_NAMES: [&str; 1] = ["ThreadPoolExecutor-68_0"];
+
+pub static DATA_KEYS: [&str; 8] = [
+    "db.statement",
+    "http.request.body",
+    "http.response.body",
+    "messaging.message.body",
+    "code.stacktrace",
+    "cache.key",
+    "graphql.document",
+    "url.full",
+];
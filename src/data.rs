@@ -1,115 +1,382 @@
+use std::collections::BTreeMap;
+use std::io;
 use std::net::Ipv4Addr;
+use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::{Context, Result};
 use fake::Fake;
 use fake::faker::filesystem::en::DirPath;
 use fake::faker::internet::en::{FreeEmail, IPv4};
-use fake::faker::lorem::en::Sentence;
-use fake::faker::time::en::DateTimeBetween;
-use rand::Rng;
-use rand::rngs::ThreadRng;
+use fake::faker::lorem::en::{Paragraph, Sentence};
+use rand::rngs::StdRng;
 use rand::seq::{IndexedRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
 use rand_distr::{Distribution, Normal};
 use serde::Serialize;
-use time::OffsetDateTime;
+use time::{OffsetDateTime, PrimitiveDateTime};
 
-use crate::cli::{Config, MAX_PROJECTS, SpanOrder};
+use crate::cli::{Config, MAX_PROJECTS, SpanOrder, ValueDistribution};
 use crate::constants::{
-    BROWSER_NAMES, HTTP_METHODS, ROOT_OPS, SENTRY_ENVIRONMENTS, SENTRY_PLATFORMS, SENTRY_RELEASES,
-    SENTRY_SDKS, SENTRY_TRANSACTIONS, SPAN_OPS, THREAD_NAMES,
+    BROWSER_NAMES, DATA_KEYS, HTTP_METHODS, ROOT_OPS, SENTRY_PLATFORMS, SENTRY_SDKS, SPAN_OPS,
+    THREAD_NAMES,
 };
+use crate::distributions::{IdSampler, ValueSampler};
 use crate::types::{SpanId, TraceId};
 
-#[derive(Clone, Debug, Default)]
+/// The size of the lorem ipsum text that span payloads are padded with.
+const FILLER_SIZE: usize = 64 * 1024;
+
+/// The maximum length of a single filler data attribute, except for the last one.
+const MAX_DATA_VALUE: usize = 4096;
+
+/// The size of `,"data":{}` that is added to a span when it is padded.
+const DATA_OVERHEAD: usize = 10;
+
+/// The maximum number of segments per trace. Heavy-tailed distributions are cut off here, so that a
+/// single sample cannot exhaust memory.
+const MAX_SEGMENTS_PER_TRACE: f64 = 1_000.0;
+
+/// The maximum number of spans per segment.
+const MAX_SPANS_PER_SEGMENT: f64 = 10_000.0;
+
+/// The maximum sampled duration, delay or lag in milliseconds, which is one day.
+const MAX_DURATION_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Values below this limit are counted exactly in a [`Histogram`].
+const EXACT_BUCKETS: u64 = 64;
+
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct Stats {
     pub spans: usize,
     pub segments: usize,
     pub traces: usize,
+    /// The number of messages written to the sink.
+    pub messages: usize,
+    /// The total size of all messages written to the sink in bytes.
+    pub bytes: usize,
+    /// The number of messages that the sink failed to deliver.
+    pub delivery_errors: usize,
+    /// The number of messages accepted by the HTTP endpoint.
+    pub accepted: usize,
+    /// The number of messages rejected by the HTTP endpoint.
+    pub rejected: usize,
+    /// The number of rate limited HTTP requests, including retries.
+    pub rate_limited: usize,
+    /// The number of messages that violate the schema.
+    pub invalid: usize,
+    /// The average rate in spans per second that the throughput schedule allowed, or 0 if the
+    /// throughput is not limited.
+    pub target_rate: f64,
+    /// The average rate in spans per second at which spans were emitted.
+    pub achieved_rate: f64,
+    /// The number of spans per organization ID.
+    pub organizations: BTreeMap<u64, usize>,
+    /// The number of spans per project ID.
+    pub projects: BTreeMap<u64, usize>,
+    /// The number of spans emitted per segment, excluding omitted root spans.
+    pub spans_per_segment: Histogram,
+    /// The number of segments per trace.
+    pub segments_per_trace: Histogram,
+    /// The depth of the span tree of every segment, where a segment with only a root span has a
+    /// depth of 0.
+    pub tree_depth: Histogram,
+    /// The size of messages written to the sink in bytes.
+    pub message_size: Histogram,
+}
+
+impl Stats {
+    /// Adds the counters of `other`, for example from another worker.
+    pub fn add(&mut self, other: &Stats) {
+        self.spans += other.spans;
+        self.segments += other.segments;
+        self.traces += other.traces;
+        self.messages += other.messages;
+        self.bytes += other.bytes;
+        self.delivery_errors += other.delivery_errors;
+        self.accepted += other.accepted;
+        self.rejected += other.rejected;
+        self.rate_limited += other.rate_limited;
+        self.invalid += other.invalid;
+        self.target_rate += other.target_rate;
+        self.achieved_rate += other.achieved_rate;
+
+        for (&id, &spans) in &other.organizations {
+            *self.organizations.entry(id).or_default() += spans;
+        }
+        for (&id, &spans) in &other.projects {
+            *self.projects.entry(id).or_default() += spans;
+        }
+
+        self.spans_per_segment.add(&other.spans_per_segment);
+        self.segments_per_trace.add(&other.segments_per_trace);
+        self.tree_depth.add(&other.tree_depth);
+        self.message_size.add(&other.message_size);
+    }
+
+    /// Records a message with `len` bytes that was written to the sink.
+    pub fn record_message(&mut self, len: usize) {
+        self.messages += 1;
+        self.bytes += len;
+        self.message_size.record(len as u64);
+    }
+}
+
+/// The distribution of a quantity such as the number of spans per segment.
+///
+/// Values below 64 are counted exactly, larger values are grouped into buckets of powers of two.
+/// Buckets are keyed by their lower bound.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum: u64,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub buckets: BTreeMap<u64, u64>,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        let bucket = if value < EXACT_BUCKETS {
+            value
+        } else {
+            1 << value.ilog2()
+        };
+
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        *self.buckets.entry(bucket).or_default() += 1;
+    }
+
+    /// Adds all values recorded in `other`.
+    pub fn add(&mut self, other: &Histogram) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+        for (&bucket, &count) in &other.buckets {
+            *self.buckets.entry(bucket).or_default() += count;
+        }
+    }
 }
 
 pub struct RandomGenerator<'a> {
     config: &'a Config,
-    rng: ThreadRng,
-    segment_dist: Normal<f64>,
-    span_dist: Normal<f64>,
-    #[allow(dead_code, reason = "TODO: support custom receive time")]
-    batch_delay_dist: Normal<f64>,
+    rng: StdRng,
+    org_sampler: IdSampler,
+    project_sampler: IdSampler,
+    segment_dist: ValueSampler,
+    span_dist: ValueSampler,
+    batch_delay_dist: ValueSampler,
+    ingestion_lag_dist: ValueSampler,
+    root_duration_dist: Option<ValueSampler>,
+    payload_size_dist: Normal<f64>,
+    filler: String,
     stats: Stats,
 }
 
 impl<'a> RandomGenerator<'a> {
-    pub fn new(config: &'a Config) -> Self {
-        let segment_dist = Normal::new(
+    pub fn new(config: &'a Config) -> Result<Self> {
+        let org_sampler = IdSampler::new(&config.org_distribution, config.orgs)
+            .context("invalid org distribution")?;
+        let project_sampler = IdSampler::new(&config.project_distribution, config.projects)
+            .context("invalid project distribution")?;
+
+        let segment_dist = value_sampler(
+            &config.segments_per_trace_distribution,
             config.segments_per_trace as f64,
             config.segments_per_trace_stddev,
         )
-        .unwrap();
+        .context("invalid segments-per-trace distribution")?;
 
-        let span_dist = Normal::new(
+        let span_dist = value_sampler(
+            &config.spans_per_segment_distribution,
             config.spans_per_segment as f64,
             config.spans_per_segment_stddev,
         )
-        .unwrap();
+        .context("invalid spans-per-segment distribution")?;
 
-        let batch_delay_dist = Normal::new(
+        let batch_delay_dist = value_sampler(
+            &config.batch_delay_distribution,
             config.batch_delay_ms as f64,
             config.batch_delay_stddev as f64,
         )
+        .context("invalid batch delay distribution")?;
+
+        let ingestion_lag_dist = value_sampler(
+            &config.ingestion_lag_distribution,
+            config.ingestion_lag_ms as f64,
+            config.ingestion_lag_stddev as f64,
+        )
+        .context("invalid ingestion lag distribution")?;
+
+        let root_duration_dist = config
+            .root_duration_distribution
+            .as_ref()
+            .map(ValueSampler::new)
+            .transpose()
+            .context("invalid root duration distribution")?;
+
+        let payload_size_dist = Normal::new(
+            config.payload_size as f64,
+            config.payload_size_stddev as f64,
+        )
         .unwrap();
 
-        Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        let mut filler = String::with_capacity(FILLER_SIZE);
+        while filler.len() < FILLER_SIZE {
+            // sentences are separated by newlines, which would be escaped in JSON
+            let paragraph: String = Paragraph(3..10).fake_with_rng(&mut rng);
+            filler.push_str(&paragraph.replace('\n', " "));
+            filler.push(' ');
+        }
+
+        Ok(Self {
             config,
-            rng: rand::rng(),
+            rng,
+            org_sampler,
+            project_sampler,
             segment_dist,
             span_dist,
             batch_delay_dist,
+            ingestion_lag_dist,
+            root_duration_dist,
+            payload_size_dist,
+            filler,
             stats: Stats::default(),
-        }
+        })
     }
 
     pub fn stats(&self) -> &Stats {
         &self.stats
     }
 
-    pub fn rng(&mut self) -> &mut ThreadRng {
+    pub fn rng(&mut self) -> &mut StdRng {
         &mut self.rng
     }
 
     pub fn organization_id(&mut self) -> u64 {
-        self.rng.random_range(1..self.config.orgs + 1)
+        self.org_sampler.sample(&mut self.rng)
     }
 
     pub fn project_id(&mut self, organization_id: u64) -> u64 {
-        self.rng.random_range(1..self.config.projects + 1) + (organization_id - 1) * MAX_PROJECTS
+        self.project_sampler.sample(&mut self.rng) + (organization_id - 1) * MAX_PROJECTS
     }
 
     pub fn segment_count(&mut self) -> usize {
-        self.segment_dist.sample(&mut self.rng).round().max(1.0) as usize
+        let count = clamp_count(
+            self.segment_dist.sample(&mut self.rng),
+            MAX_SEGMENTS_PER_TRACE,
+        );
+        self.stats.segments_per_trace.record(count as u64);
+        count
     }
 
     pub fn span_count(&mut self) -> usize {
-        self.span_dist.sample(&mut self.rng).round().max(1.0) as usize
+        clamp_count(self.span_dist.sample(&mut self.rng), MAX_SPANS_PER_SEGMENT)
     }
 
-    pub fn trace(&mut self) -> TraceInfo {
+    /// Returns `len` bytes of lorem ipsum text starting at a random position.
+    fn filler(&mut self, len: usize) -> String {
+        let mut value = String::with_capacity(len);
+        let mut offset = self.rng.random_range(0..self.filler.len());
+
+        while value.len() < len {
+            let end = (offset + len - value.len()).min(self.filler.len());
+            value.push_str(&self.filler[offset..end]);
+            offset = 0;
+        }
+
+        value
+    }
+
+    /// Adds filler data attributes to the span until its serialized size reaches the sampled
+    /// payload size.
+    pub fn pad(&mut self, span: &mut Span) {
+        if self.config.payload_size == 0 {
+            return;
+        }
+
+        let target = self
+            .payload_size_dist
+            .sample(&mut self.rng)
+            .round()
+            .max(0.0) as usize;
+        let mut remaining = target.saturating_sub(serialized_size(span));
+
+        // Account for `,"data":{}` when the first attribute is added.
+        if !span.data.is_empty() || remaining <= DATA_OVERHEAD {
+            return;
+        }
+        remaining -= DATA_OVERHEAD;
+
+        for (index, key) in DATA_KEYS.iter().enumerate() {
+            // `"key":"value"` plus a separating comma for all but the first attribute
+            let overhead = key.len() + 5 + (index > 0) as usize;
+            if remaining <= overhead {
+                break;
+            }
+
+            let len = if index == DATA_KEYS.len() - 1 {
+                remaining - overhead
+            } else {
+                (remaining - overhead).min(MAX_DATA_VALUE)
+            };
+
+            let value = self.filler(len);
+            span.data.insert(key, value);
+            remaining -= overhead + len;
+        }
+    }
+
+    /// Samples the delay between two consecutive batches of a segment.
+    pub fn batch_delay(&mut self) -> Duration {
+        let delay_ms = clamp_ms(self.batch_delay_dist.sample(&mut self.rng).round());
+        Duration::from_millis(delay_ms as u64)
+    }
+
+    /// Returns the time at which a batch of the segment is received by the ingestion pipeline.
+    ///
+    /// The batch is flushed `flush_delay` after the end of the segment and then arrives after a
+    /// randomized ingestion lag.
+    pub fn received(&mut self, segment: &SegmentInfo, flush_delay: Duration) -> OffsetDateTime {
+        let lag_ms = clamp_ms(self.ingestion_lag_dist.sample(&mut self.rng).round());
+        let flushed = saturating_add(segment.end_timestamp, flush_delay);
+        saturating_add(flushed, Duration::from_millis(lag_ms as u64))
+    }
+
+    pub fn trace(&mut self, timestamp: OffsetDateTime) -> TraceInfo {
         self.stats.traces += 1;
-        TraceInfo::new(self.organization_id())
+        TraceInfo::new(self.rng.random(), self.organization_id(), timestamp)
     }
 
     pub fn sentry_tags(&mut self) -> SentryTags {
         let user_id = self.rng.random_range(1..100_000);
-        let user_email: String = FreeEmail().fake();
+        let user_email: String = FreeEmail().fake_with_rng(&mut self.rng);
+        let dictionaries = &self.config.dictionaries;
 
         SentryTags {
-            release: SENTRY_RELEASES.choose(self.rng()).unwrap(),
+            release: dictionaries.releases.choose(&mut self.rng).unwrap().clone(),
             user: user_id,
             user_id,
-            user_ip: IPv4().fake(),
+            user_ip: IPv4().fake_with_rng(&mut self.rng),
             user_username: user_email.clone(),
             user_email,
-            environment: SENTRY_ENVIRONMENTS.choose(self.rng()).unwrap(),
+            environment: dictionaries
+                .environments
+                .choose(&mut self.rng)
+                .unwrap()
+                .clone(),
             op: SPAN_OPS.choose(self.rng()).unwrap(),
-            transaction: SENTRY_TRANSACTIONS.choose(self.rng()).unwrap(),
+            transaction: dictionaries
+                .transactions
+                .choose(&mut self.rng)
+                .unwrap()
+                .clone(),
             transaction_method: HTTP_METHODS.choose(self.rng()).unwrap(),
             transaction_op: ROOT_OPS.choose(self.rng()).unwrap(),
             browser_name: BROWSER_NAMES.choose(self.rng()).unwrap(),
@@ -125,45 +392,164 @@ impl<'a> RandomGenerator<'a> {
         }
     }
 
-    pub fn segment<'b>(&mut self, trace: &'b TraceInfo) -> SegmentInfo<'b> {
+    /// Samples the duration of a root span that is not nested into a remote parent.
+    fn root_duration(&mut self) -> Duration {
+        match self.root_duration_dist {
+            Some(ref dist) => {
+                let duration_ms = clamp_ms(dist.sample(&mut self.rng)).max(0.001);
+                Duration::from_secs_f64(duration_ms / 1000.0)
+            }
+            None => Duration::from_micros(self.rng.random_range(1_000..2_000_000)),
+        }
+    }
+
+    /// Samples an interval for a child nested into the given parent interval.
+    fn nested_interval(
+        &mut self,
+        start: OffsetDateTime,
+        duration: Duration,
+    ) -> (OffsetDateTime, Duration) {
+        let child_duration = duration.mul_f64(self.rng.random_range(0.1..=1.0));
+        let offset = (duration - child_duration).mul_f64(self.rng.random());
+        (start + offset, child_duration)
+    }
+
+    /// Creates a new segment in the trace.
+    ///
+    /// If the segment continues from a remote parent span, it starts and ends within that span.
+    /// Otherwise, the segment ends at the trace's timestamp.
+    pub fn segment(&mut self, trace: &TraceInfo, remote_parent: Option<&SpanRef>) -> SegmentInfo {
         self.stats.segments += 1;
+
+        let (start_timestamp, duration) = match remote_parent {
+            Some(parent) => self.nested_interval(parent.start_timestamp, parent.duration),
+            None => {
+                let duration = self.root_duration();
+                (saturating_sub(trace.timestamp, duration), duration)
+            }
+        };
+
         SegmentInfo::new(
-            trace,
+            *trace,
             self.project_id(trace.organization_id),
+            self.rng.random(),
             self.sentry_tags(),
+            start_timestamp,
+            saturating_add(start_timestamp, duration),
         )
     }
 
     /// Builds a randomized span tree with defined number of spans and depth.
     ///
-    /// The tree is returned serialized in post-order: children first and then their parents.
-    pub fn span_refs(&mut self, segment: &SegmentInfo<'_>) -> Vec<SpanRef> {
+    /// The root span covers the entire segment. Children are nested into the interval of their
+    /// parent and laid out either sequentially or in parallel.
+    ///
+    /// The tree is returned serialized in the configured order. For the configured percentage of
+    /// segments, the root span is omitted.
+    pub fn span_refs(&mut self, segment: &SegmentInfo) -> Vec<SpanRef> {
         let len = self.span_count();
         let depth = self.config.tree_depth;
 
         let mut levels = Vec::with_capacity(len);
+        let mut children = Vec::with_capacity(len);
         let mut spans = Vec::with_capacity(len);
 
         levels.push(0);
+        children.push(Vec::new());
         spans.push(SpanRef {
             span_id: segment.span_id,
             parent_id: None,
+            start_timestamp: segment.start_timestamp,
+            duration: (segment.end_timestamp - segment.start_timestamp).unsigned_abs(),
+            exclusive_time: Duration::ZERO,
         });
 
         while spans.len() < len {
-            let mut index = rand::random_range(0..spans.len());
+            let mut index = self.rng.random_range(0..spans.len());
 
             while levels[index] >= depth {
                 index -= 1;
             }
 
             levels.push(levels[index] + 1);
+            children.push(Vec::new());
+            children[index].push(spans.len());
             spans.push(SpanRef {
-                span_id: SpanId::default(),
+                span_id: self.rng.random(),
                 parent_id: Some(spans[index].span_id),
+                start_timestamp: segment.start_timestamp,
+                duration: Duration::ZERO,
+                exclusive_time: Duration::ZERO,
             });
         }
 
+        // Parents always precede their children, so their intervals are known by the time the
+        // children are laid out.
+        for (index, children) in children.iter().enumerate() {
+            if children.is_empty() {
+                continue;
+            }
+
+            let SpanRef {
+                start_timestamp,
+                duration,
+                ..
+            } = spans[index];
+
+            if self.rng.random_ratio(1, 2) {
+                // sequential: every child gets an equal slot of the parent's interval
+                let slot = duration / children.len() as u32;
+                for (position, &child) in children.iter().enumerate() {
+                    let slot_start = start_timestamp + slot * position as u32;
+                    let (start, duration) = self.nested_interval(slot_start, slot);
+                    spans[child].start_timestamp = start;
+                    spans[child].duration = duration;
+                }
+            } else {
+                // parallel: every child can span the entire interval of the parent
+                for &child in children {
+                    let (start, duration) = self.nested_interval(start_timestamp, duration);
+                    spans[child].start_timestamp = start;
+                    spans[child].duration = duration;
+                }
+            }
+        }
+
+        // The exclusive time of a span is the part of its interval that none of its children cover.
+        for (index, children) in children.iter().enumerate() {
+            let mut intervals: Vec<_> = children
+                .iter()
+                .map(|&child| {
+                    let start = spans[child].start_timestamp;
+                    (start, saturating_add(start, spans[child].duration))
+                })
+                .collect();
+            intervals.sort_unstable();
+
+            let mut covered = Duration::ZERO;
+            let mut covered_until = spans[index].start_timestamp;
+            for (start, end) in intervals {
+                let start = start.max(covered_until);
+                if end > start {
+                    covered += (end - start).unsigned_abs();
+                    covered_until = end;
+                }
+            }
+
+            spans[index].exclusive_time = spans[index].duration.saturating_sub(covered);
+        }
+
+        let tree_depth = levels.iter().copied().max().unwrap_or_default();
+        self.stats.tree_depth.record(tree_depth as u64);
+
+        // SDKs may never send the root span of a segment. Its children still point to it.
+        let without_root = u32::from(self.config.segments_without_root);
+        if spans.len() > 1 && self.rng.random_ratio(without_root, 100) {
+            spans.remove(0);
+        }
+
+        self.stats.spans_per_segment.record(spans.len() as u64);
+
         match self.config.order {
             SpanOrder::Post => spans.reverse(),
             SpanOrder::Pre => (),
@@ -173,15 +559,22 @@ impl<'a> RandomGenerator<'a> {
         spans
     }
 
-    pub fn span<'s>(&mut self, segment: &'s SegmentInfo<'_>, span_ref: SpanRef) -> Span<'s> {
+    pub fn span(
+        &mut self,
+        segment: &SegmentInfo,
+        span_ref: SpanRef,
+        received: OffsetDateTime,
+    ) -> Span {
         self.stats.spans += 1;
+        *self
+            .stats
+            .organizations
+            .entry(segment.trace.organization_id)
+            .or_default() += 1;
+        *self.stats.projects.entry(segment.project_id).or_default() += 1;
 
-        let now = OffsetDateTime::now_utc();
-        let lower = now - Duration::from_secs(60 * 60);
-
-        let end_timestamp = DateTimeBetween(lower, now).fake();
-        let duration_ms: u32 = (1..2000).fake();
-        let start_timestamp = end_timestamp - Duration::from_millis(duration_ms.into());
+        let start_timestamp = span_ref.start_timestamp;
+        let end_timestamp = start_timestamp + span_ref.duration;
 
         Span {
             trace_id: segment.trace.trace_id,
@@ -189,68 +582,95 @@ impl<'a> RandomGenerator<'a> {
             parent_span_id: span_ref.parent_id,
             segment_id: Some(segment.span_id),
             is_remote: false,
+            is_segment: span_ref.parent_id.is_none(),
             organization_id: segment.trace.organization_id,
             project_id: segment.project_id,
 
-            description: Sentence(3..6).fake(),
-            origin: DirPath().fake(),
-            sentry_tags: &segment.sentry_tags,
-            received: to_float(now),
+            description: Sentence(3..6).fake_with_rng(&mut self.rng),
+            origin: DirPath().fake_with_rng(&mut self.rng),
+            sentry_tags: Arc::clone(&segment.sentry_tags),
+            received: to_float(received),
             start_timestamp_precise: to_float(start_timestamp),
             end_timestamp_precise: to_float(end_timestamp),
-            start_timestamp_ms: start_timestamp.unix_timestamp() as u64 * 1000,
-            duration_ms,
+            start_timestamp_ms: (start_timestamp.unix_timestamp_nanos() / 1_000_000) as u64,
+            duration_ms: span_ref.duration.as_millis() as u32,
+            exclusive_time_ms: span_ref.exclusive_time.as_secs_f64() * 1000.0,
             platform: "other",
             retention_days: 30,
+            data: BTreeMap::new(),
+            start_timestamp,
+            end_timestamp,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct TraceInfo {
     pub trace_id: TraceId,
     pub organization_id: u64,
+    /// The time at which the trace is generated on the run's timeline.
+    pub timestamp: OffsetDateTime,
 }
 
 impl TraceInfo {
-    pub fn new(organization_id: u64) -> Self {
+    pub fn new(trace_id: TraceId, organization_id: u64, timestamp: OffsetDateTime) -> Self {
         Self {
-            trace_id: TraceId::default(),
+            trace_id,
             organization_id,
+            timestamp,
         }
     }
 }
 
-pub struct SegmentInfo<'a> {
-    pub trace: &'a TraceInfo,
+pub struct SegmentInfo {
+    pub trace: TraceInfo,
     pub project_id: u64,
     pub span_id: SpanId,
-    pub sentry_tags: SentryTags,
+    /// The tags shared by all spans of the segment.
+    pub sentry_tags: Arc<SentryTags>,
+    /// The time at which the root span of the segment starts.
+    pub start_timestamp: OffsetDateTime,
+    /// The time at which the root span of the segment ends and the SDK starts flushing it.
+    pub end_timestamp: OffsetDateTime,
 }
 
-impl<'a> SegmentInfo<'a> {
-    pub fn new(trace: &'a TraceInfo, project_id: u64, sentry_tags: SentryTags) -> Self {
+impl SegmentInfo {
+    pub fn new(
+        trace: TraceInfo,
+        project_id: u64,
+        span_id: SpanId,
+        sentry_tags: SentryTags,
+        start_timestamp: OffsetDateTime,
+        end_timestamp: OffsetDateTime,
+    ) -> Self {
         Self {
             trace,
             project_id,
-            span_id: SpanId::default(),
-            sentry_tags,
+            span_id,
+            sentry_tags: Arc::new(sentry_tags),
+            start_timestamp,
+            end_timestamp,
         }
     }
 }
 
-/// A span id and its optional parent id.
+/// A span id, its optional parent id and the interval it covers.
 #[derive(Debug, Clone, Copy)]
 pub struct SpanRef {
     pub span_id: SpanId,
     pub parent_id: Option<SpanId>,
+    pub start_timestamp: OffsetDateTime,
+    pub duration: Duration,
+    /// The part of the interval that is not covered by any child span.
+    pub exclusive_time: Duration,
 }
 
 #[derive(Debug, Serialize)]
 pub struct SentryTags {
-    pub release: &'static str,
+    pub release: String,
     #[serde(serialize_with = "serialize_user")]
     pub user: u32,
-    #[serde(rename = "user.id")]
+    #[serde(rename = "user.id", serialize_with = "serialize_display")]
     pub user_id: u32,
     #[serde(rename = "user.ip")]
     pub user_ip: Ipv4Addr,
@@ -258,9 +678,9 @@ pub struct SentryTags {
     pub user_username: String,
     #[serde(rename = "user.email")]
     pub user_email: String,
-    pub environment: &'static str,
+    pub environment: String,
     pub op: &'static str,
-    pub transaction: &'static str,
+    pub transaction: String,
     #[serde(rename = "transaction.method")]
     pub transaction_method: &'static str,
     #[serde(rename = "transaction.op")]
@@ -272,7 +692,7 @@ pub struct SentryTags {
     #[serde(rename = "sdk.version", serialize_with = "serialize_version")]
     pub sdk_version: (u8, u8, u8),
     pub platform: &'static str,
-    #[serde(rename = "thread.id")]
+    #[serde(rename = "thread.id", serialize_with = "serialize_display")]
     pub thread_id: u32,
     #[serde(rename = "thread.name")]
     pub thread_name: &'static str,
@@ -282,6 +702,14 @@ fn serialize_user<S: serde::Serializer>(user: &u32, serializer: S) -> Result<S::
     serializer.collect_str(&format_args!("id:{user}"))
 }
 
+/// Serializes numeric tags as strings, since all values in `sentry_tags` are strings.
+fn serialize_display<S: serde::Serializer>(
+    value: &impl std::fmt::Display,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
 fn serialize_version<S: serde::Serializer>(
     &(major, minor, patch): &(u8, u8, u8),
     serializer: S,
@@ -291,24 +719,54 @@ fn serialize_version<S: serde::Serializer>(
 
 /// A complete span populated with fake data.
 #[derive(Debug, Serialize)]
-pub struct Span<'a> {
+pub struct Span {
     pub trace_id: TraceId,
     pub span_id: SpanId,
     pub parent_span_id: Option<SpanId>,
     pub segment_id: Option<SpanId>,
     pub is_remote: bool,
+    pub is_segment: bool,
     pub organization_id: u64,
     pub project_id: u64,
     pub description: String,
     pub origin: String,
-    pub sentry_tags: &'a SentryTags,
+    pub sentry_tags: Arc<SentryTags>,
     pub received: f64,
     pub start_timestamp_precise: f64,
     pub end_timestamp_precise: f64,
     pub start_timestamp_ms: u64,
     pub duration_ms: u32,
+    pub exclusive_time_ms: f64,
     pub platform: &'static str,
     pub retention_days: u16,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub data: BTreeMap<&'static str, String>,
+    /// The exact start time, which `start_timestamp_precise` only approximates.
+    #[serde(skip)]
+    pub start_timestamp: OffsetDateTime,
+    /// The exact end time, which `end_timestamp_precise` only approximates.
+    #[serde(skip)]
+    pub end_timestamp: OffsetDateTime,
+}
+
+/// Returns the length of the JSON serialization of `value` without allocating it.
+fn serialized_size<T: Serialize>(value: &T) -> usize {
+    struct Counter(usize);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, value).expect("span serialization is infallible");
+    counter.0
 }
 
 fn to_float(date_time: OffsetDateTime) -> f64 {
@@ -316,3 +774,206 @@ fn to_float(date_time: OffsetDateTime) -> f64 {
     let subsecond = date_time.nanosecond() as f64 / 1_000_000_000.0;
     timestamp + subsecond
 }
+
+/// Rounds a sampled count and clamps it to `1..=max`. NaN becomes 1.
+fn clamp_count(count: f64, max: f64) -> usize {
+    if count.is_nan() {
+        return 1;
+    }
+
+    count.round().clamp(1.0, max) as usize
+}
+
+/// Clamps a sampled number of milliseconds to `0..=MAX_DURATION_MS`. NaN becomes 0.
+fn clamp_ms(ms: f64) -> f64 {
+    if ms.is_nan() {
+        return 0.0;
+    }
+
+    ms.clamp(0.0, MAX_DURATION_MS)
+}
+
+/// Adds `duration` to `timestamp`, saturating at the latest representable timestamp.
+fn saturating_add(timestamp: OffsetDateTime, duration: Duration) -> OffsetDateTime {
+    time::Duration::try_from(duration)
+        .ok()
+        .and_then(|duration| timestamp.checked_add(duration))
+        .unwrap_or_else(|| PrimitiveDateTime::MAX.assume_utc())
+}
+
+/// Subtracts `duration` from `timestamp`, saturating at the earliest representable timestamp.
+fn saturating_sub(timestamp: OffsetDateTime, duration: Duration) -> OffsetDateTime {
+    time::Duration::try_from(duration)
+        .ok()
+        .and_then(|duration| timestamp.checked_sub(duration))
+        .unwrap_or_else(|| PrimitiveDateTime::MIN.assume_utc())
+}
+
+/// Creates a sampler for the given distribution, or a normal distribution if none is configured.
+fn value_sampler(
+    distribution: &Option<ValueDistribution>,
+    mean: f64,
+    stddev: f64,
+) -> Result<ValueSampler> {
+    match distribution {
+        Some(distribution) => ValueSampler::new(distribution),
+        None => ValueSampler::new(&ValueDistribution::Normal { mean, stddev }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::OpenTrace;
+
+    /// Distributions with parameters that produce huge, infinite or negative samples.
+    const EXTREME_DISTRIBUTIONS: &[&str] = &[
+        "constant:1e300",
+        "constant:-1e300",
+        "uniform:0:1e300",
+        "normal:0:1e300",
+        "lognormal:1e300:1e300",
+        "pareto:1000:0.05",
+        "pareto:1000:0.3",
+        "poisson:1e15",
+        "exponential:1e300",
+    ];
+
+    fn distribution(spec: &str) -> Option<ValueDistribution> {
+        Some(spec.parse().unwrap())
+    }
+
+    /// Generates traces with `config` and checks that all intervals are nested. Returns `None` if
+    /// the distributions are rejected up front.
+    fn generate(config: &Config, traces: usize) -> Option<Vec<OpenTrace>> {
+        let mut generator = RandomGenerator::new(config).ok()?;
+        let timestamp = OffsetDateTime::from_unix_timestamp(config.start_time).unwrap();
+
+        let traces: Vec<_> = (0..traces)
+            .map(|_| OpenTrace::generate(&mut generator, timestamp))
+            .collect();
+
+        for trace in &traces {
+            for segment in &trace.segments {
+                let info = &segment.info;
+                assert!(info.start_timestamp <= info.end_timestamp);
+
+                let flush_delay = generator.batch_delay();
+                let received = generator.received(info, flush_delay);
+                assert!(received >= info.end_timestamp);
+
+                for span_ref in segment.span_refs.iter().take(10) {
+                    let span = generator.span(info, *span_ref, received);
+                    assert!(span.start_timestamp_precise >= to_float(info.start_timestamp));
+                    assert!(span.end_timestamp_precise <= to_float(info.end_timestamp));
+                }
+            }
+        }
+
+        Some(traces)
+    }
+
+    #[test]
+    fn test_extreme_durations() {
+        for spec in EXTREME_DISTRIBUTIONS {
+            let config = Config {
+                root_duration_distribution: distribution(spec),
+                batch_delay_distribution: distribution(spec),
+                ingestion_lag_distribution: distribution(spec),
+                spans_per_segment: 5,
+                segments_per_trace: 3,
+                ..Config::default()
+            };
+
+            generate(&config, 100);
+        }
+    }
+
+    #[test]
+    fn test_extreme_durations_at_the_end_of_time() {
+        let config = Config {
+            start_time: PrimitiveDateTime::MAX.assume_utc().unix_timestamp(),
+            root_duration_distribution: distribution("constant:1e300"),
+            ingestion_lag_distribution: distribution("constant:1e300"),
+            ..Config::default()
+        };
+
+        generate(&config, 10);
+    }
+
+    #[test]
+    fn test_extreme_segments_per_trace() {
+        for spec in EXTREME_DISTRIBUTIONS {
+            let config = Config {
+                segments_per_trace_distribution: distribution(spec),
+                spans_per_segment_distribution: distribution("constant:1"),
+                ..Config::default()
+            };
+
+            for trace in generate(&config, 10).into_iter().flatten() {
+                let count = trace.segments.len();
+                assert!((1..=MAX_SEGMENTS_PER_TRACE as usize).contains(&count));
+            }
+        }
+    }
+
+    #[test]
+    fn test_extreme_spans_per_segment() {
+        for spec in EXTREME_DISTRIBUTIONS {
+            let config = Config {
+                spans_per_segment_distribution: distribution(spec),
+                segments_per_trace_distribution: distribution("constant:1"),
+                ..Config::default()
+            };
+
+            for trace in generate(&config, 10).into_iter().flatten() {
+                let count = trace.span_count();
+                assert!((1..=MAX_SPANS_PER_SEGMENT as usize).contains(&count));
+            }
+        }
+    }
+
+    #[test]
+    fn test_exclusive_time() {
+        let config = Config {
+            segments_without_root: 0,
+            ..Config::default()
+        };
+
+        for trace in generate(&config, 100).unwrap() {
+            for segment in &trace.segments {
+                let refs = &segment.span_refs;
+                for span_ref in refs {
+                    assert!(span_ref.exclusive_time <= span_ref.duration);
+
+                    let is_parent = refs.iter().any(|r| r.parent_id == Some(span_ref.span_id));
+                    if !is_parent {
+                        assert_eq!(span_ref.exclusive_time, span_ref.duration);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_spans_match_schema() {
+        let config = Config::default();
+        let mut generator = RandomGenerator::new(&config).unwrap();
+        let mut validator = crate::schema::SchemaValidator::new().unwrap();
+        let timestamp = OffsetDateTime::from_unix_timestamp(config.start_time).unwrap();
+
+        for _ in 0..10 {
+            let trace = OpenTrace::generate(&mut generator, timestamp);
+            for segment in &trace.segments {
+                let received = generator.received(&segment.info, Duration::ZERO);
+                for span_ref in &segment.span_refs {
+                    let span = generator.span(&segment.info, *span_ref, received);
+                    assert_eq!(span.is_segment, span_ref.span_id == segment.info.span_id);
+
+                    let payload = serde_json::to_vec(&span).unwrap();
+                    assert_eq!(validator.violations(&payload), Vec::<String>::new());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Samplers for the distributions selected in the config.
+
+use anyhow::{Context, Result};
+use rand::Rng;
+use rand::distr::Uniform;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::{Distribution, Exp, LogNormal, Normal, Pareto, Poisson, Zipf};
+
+use crate::cli::{TenantDistribution, ValueDistribution};
+
+/// Samples IDs in `1..=n` according to a [`TenantDistribution`].
+///
+/// Lower IDs receive more traffic in all skewed distributions.
+pub enum IdSampler {
+    Uniform {
+        n: u64,
+    },
+    Zipf(Zipf<f64>),
+    Hot {
+        hot: u64,
+        n: u64,
+        probability: f64,
+    },
+    Weighted {
+        /// Explicit weights, followed by the combined weight of all remaining IDs.
+        index: WeightedIndex<f64>,
+        explicit: u64,
+        n: u64,
+    },
+}
+
+impl IdSampler {
+    pub fn new(distribution: &TenantDistribution, n: u64) -> Result<Self> {
+        Ok(match *distribution {
+            TenantDistribution::Uniform => Self::Uniform { n },
+            TenantDistribution::Zipf { exponent } => Self::Zipf(Zipf::new(n as f64, exponent)?),
+            TenantDistribution::Hot { count, percent } => {
+                if count == 0 || count >= n {
+                    Self::Uniform { n }
+                } else {
+                    Self::Hot {
+                        hot: count,
+                        n,
+                        probability: percent / 100.0,
+                    }
+                }
+            }
+            TenantDistribution::Weights(ref weights) => {
+                let explicit = weights.len().min(n as usize);
+                let remaining = (n - explicit as u64) as f64;
+
+                let weights = weights[..explicit].iter().copied().chain([remaining]);
+                let index = WeightedIndex::new(weights)
+                    .map_err(|e| anyhow::anyhow!("invalid tenant weights: {e}"))?;
+
+                Self::Weighted {
+                    index,
+                    explicit: explicit as u64,
+                    n,
+                }
+            }
+        })
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> u64 {
+        match *self {
+            Self::Uniform { n } => rng.random_range(1..n + 1),
+            Self::Zipf(ref zipf) => zipf.sample(rng) as u64,
+            Self::Hot {
+                hot,
+                n,
+                probability,
+            } => {
+                if rng.random_bool(probability) {
+                    rng.random_range(1..hot + 1)
+                } else {
+                    rng.random_range(hot + 1..n + 1)
+                }
+            }
+            Self::Weighted {
+                ref index,
+                explicit,
+                n,
+            } => match index.sample(rng) as u64 {
+                i if i < explicit => i + 1,
+                _ => rng.random_range(explicit + 1..n + 1),
+            },
+        }
+    }
+}
+
+/// Samples values according to a [`ValueDistribution`].
+pub enum ValueSampler {
+    Constant(f64),
+    Uniform(Uniform<f64>),
+    Normal(Normal<f64>),
+    LogNormal(LogNormal<f64>),
+    Pareto(Pareto<f64>),
+    Poisson(Poisson<f64>),
+    Exponential(Exp<f64>),
+    Histogram {
+        values: Vec<f64>,
+        index: WeightedIndex<f64>,
+    },
+}
+
+impl ValueSampler {
+    pub fn new(distribution: &ValueDistribution) -> Result<Self> {
+        Ok(match *distribution {
+            ValueDistribution::Constant { value } => Self::Constant(value),
+            ValueDistribution::Uniform { min, max } => {
+                Self::Uniform(Uniform::new_inclusive(min, max)?)
+            }
+            ValueDistribution::Normal { mean, stddev } => Self::Normal(Normal::new(mean, stddev)?),
+            ValueDistribution::LogNormal { mean, stddev } => {
+                Self::LogNormal(LogNormal::from_mean_cv(mean, stddev / mean)?)
+            }
+            ValueDistribution::Pareto { scale, shape } => Self::Pareto(Pareto::new(scale, shape)?),
+            ValueDistribution::Poisson { mean } => Self::Poisson(Poisson::new(mean)?),
+            ValueDistribution::Exponential { mean } => Self::Exponential(Exp::new(1.0 / mean)?),
+            ValueDistribution::Histogram { ref path } => std::fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| Self::histogram(&contents))
+                .with_context(|| format!("failed to read histogram {}", path.display()))?,
+        })
+    }
+
+    /// Creates a sampler from the contents of a histogram file, see [`parse_histogram`].
+    fn histogram(contents: &str) -> Result<Self> {
+        let (values, weights) = parse_histogram(contents)?;
+        let index = WeightedIndex::new(weights)
+            .map_err(|e| anyhow::anyhow!("invalid histogram weights: {e}"))?;
+        Ok(Self::Histogram { values, index })
+    }
+}
+
+impl Distribution<f64> for ValueSampler {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Uniform(uniform) => uniform.sample(rng),
+            Self::Normal(normal) => normal.sample(rng),
+            Self::LogNormal(log_normal) => log_normal.sample(rng),
+            Self::Pareto(pareto) => pareto.sample(rng),
+            Self::Poisson(poisson) => poisson.sample(rng),
+            Self::Exponential(exp) => exp.sample(rng),
+            Self::Histogram { values, index } => values[index.sample(rng)],
+        }
+    }
+}
+
+/// Parses CSV with a value and its weight on every line.
+///
+/// Empty lines, comments starting with `#` and a header line are skipped.
+fn parse_histogram(contents: &str) -> Result<(Vec<f64>, Vec<f64>)> {
+    let mut values = Vec::new();
+    let mut weights = Vec::new();
+    let mut first = true;
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = line.split_once(',').and_then(|(value, weight)| {
+            Some((value.trim().parse().ok()?, weight.trim().parse().ok()?))
+        });
+
+        match parsed {
+            Some((value, weight)) => {
+                values.push(value);
+                weights.push(weight);
+            }
+            None if first => (),
+            None => anyhow::bail!("invalid histogram entry on line {}", number + 1),
+        }
+
+        first = false;
+    }
+
+    Ok((values, weights))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    const SAMPLES: usize = 10_000;
+
+    /// Samples `n` IDs and returns how often every ID was sampled, indexed by `id - 1`.
+    fn histogram(distribution: &str, n: u64) -> Vec<usize> {
+        let sampler = IdSampler::new(&distribution.parse().unwrap(), n).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut counts = vec![0; n as usize];
+        for _ in 0..SAMPLES {
+            let id = sampler.sample(&mut rng);
+            assert!((1..=n).contains(&id), "{distribution}: {id}");
+            counts[id as usize - 1] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_uniform_covers_all_ids() {
+        let counts = histogram("uniform", 10);
+        assert!(counts.iter().all(|&count| count > SAMPLES / 20));
+    }
+
+    #[test]
+    fn test_zipf_prefers_low_ids() {
+        let counts = histogram("zipf:1", 100);
+        assert!(counts[0] > counts[1]);
+        assert!(counts[1] > counts[9]);
+        assert!(counts[9] > counts[99]);
+    }
+
+    #[test]
+    fn test_hot_tenants() {
+        let counts = histogram("hot:2:90", 10);
+        let hot: usize = counts[..2].iter().sum();
+        assert!((8_800..=9_200).contains(&hot), "{hot}");
+        assert!(counts[2..].iter().all(|&count| count > 0));
+    }
+
+    #[test]
+    fn test_hot_tenants_without_cold_tenants() {
+        // With all tenants hot, there is no cold range to sample from.
+        let counts = histogram("hot:10:90", 10);
+        assert!(counts.iter().all(|&count| count > 0));
+
+        let counts = histogram("hot:0:90", 10);
+        assert!(counts.iter().all(|&count| count > 0));
+    }
+
+    #[test]
+    fn test_weights() {
+        // ID 1 has a weight of 6 and ID 2 a weight of 0. The 2 remaining IDs have a weight of 1.
+        let counts = histogram("weights:6,0", 4);
+        assert!((7_200..=7_800).contains(&counts[0]), "{}", counts[0]);
+        assert_eq!(counts[1], 0);
+        assert!(counts[2] > 1_000 && counts[3] > 1_000);
+    }
+
+    #[test]
+    fn test_weights_for_more_ids_than_tenants() {
+        let counts = histogram("weights:1,2,3", 2);
+        assert!(counts[1] > counts[0]);
+    }
+
+    #[test]
+    fn test_parse_histogram() {
+        let (values, weights) =
+            parse_histogram("spans,count\n# single spans are common\n\n1, 90\n 10 ,9\n1000,1\n")
+                .unwrap();
+        assert_eq!(values, [1.0, 10.0, 1000.0]);
+        assert_eq!(weights, [90.0, 9.0, 1.0]);
+    }
+
+    #[test]
+    fn test_parse_histogram_without_header() {
+        assert_eq!(parse_histogram("5,1\n").unwrap(), (vec![5.0], vec![1.0]));
+    }
+
+    #[test]
+    fn test_parse_histogram_invalid_entry() {
+        // Only the first line may be a header.
+        let error = parse_histogram("spans,count\n1,2\nspans,count\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid histogram entry on line 3");
+
+        assert!(parse_histogram("1,2\n3\n").is_err());
+    }
+
+    #[test]
+    fn test_histogram_sampler() {
+        let sampler = ValueSampler::histogram("1,0\n7,1\n").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!((0..100).all(|_| sampler.sample(&mut rng) == 7.0));
+
+        assert!(ValueSampler::histogram("spans,count\n").is_err());
+
+        let distribution = ValueDistribution::Histogram {
+            path: "does-not-exist.csv".into(),
+        };
+        assert!(ValueSampler::new(&distribution).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use prost::Message;
+
+use crate::cli::{Config, OutputFormat};
+use crate::data::Span;
+use crate::{envelope, otlp};
+
+/// Encodes batches of spans into messages in the configured output format.
+pub struct Encoder {
+    format: OutputFormat,
+    relay_url: String,
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            format: config.format,
+            relay_url: config.relay_url.clone(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Encodes a batch of spans and passes every resulting message to `emit`, along with the spans
+    /// it contains.
+    pub fn encode<F>(&mut self, spans: &[Span], mut emit: F) -> Result<()>
+    where
+        F: FnMut(&[Span], &[u8]) -> Result<()>,
+    {
+        match self.format {
+            OutputFormat::Snuba => {
+                for span in spans {
+                    self.buffer.clear();
+                    serde_json::to_writer(&mut self.buffer, span)?;
+                    emit(std::slice::from_ref(span), &self.buffer)?;
+                }
+            }
+            OutputFormat::OtlpJson => {
+                self.buffer.clear();
+                serde_json::to_writer(&mut self.buffer, &otlp::export_request(spans))?;
+                emit(spans, &self.buffer)?;
+            }
+            OutputFormat::OtlpProto => {
+                self.buffer.clear();
+                otlp::export_request(spans).encode(&mut self.buffer)?;
+                emit(spans, &self.buffer)?;
+            }
+            OutputFormat::Envelope => {
+                self.buffer.clear();
+                envelope::write_envelope(&mut self.buffer, &self.relay_url, spans)?;
+                emit(spans, &self.buffer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
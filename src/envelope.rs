@@ -0,0 +1,163 @@
+//! Sentry envelopes with span items, as sent by SDKs to Relay.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::data::Span;
+use crate::types::{SpanId, TraceId};
+
+/// Returns the public key of the DSN for a project.
+///
+/// Keys are derived from the project ID, so they are stable across runs.
+pub fn public_key(project_id: u64) -> String {
+    format!("{project_id:032x}")
+}
+
+/// Returns the DSN of a project at the given Relay URL.
+pub fn dsn(relay_url: &str, project_id: u64) -> String {
+    let (scheme, host) = relay_url.split_once("://").unwrap_or(("http", relay_url));
+    let host = host.trim_end_matches('/');
+    format!("{scheme}://{}@{host}/{project_id}", public_key(project_id))
+}
+
+#[derive(Serialize)]
+struct EnvelopeHeaders<'a> {
+    dsn: String,
+    sdk: ClientSdk<'a>,
+    trace: DynamicSamplingContext<'a>,
+}
+
+#[derive(Serialize)]
+struct ClientSdk<'a> {
+    name: &'a str,
+    version: String,
+}
+
+#[derive(Serialize)]
+struct DynamicSamplingContext<'a> {
+    trace_id: TraceId,
+    public_key: String,
+    release: &'a str,
+    environment: &'a str,
+    transaction: &'a str,
+    sample_rate: &'a str,
+    sampled: &'a str,
+}
+
+#[derive(Serialize)]
+struct ItemHeaders {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    length: usize,
+    content_type: &'static str,
+}
+
+/// A span in the format that SDKs send in span items.
+#[derive(Serialize)]
+struct SpanItem<'a> {
+    trace_id: TraceId,
+    span_id: SpanId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<SpanId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segment_id: Option<SpanId>,
+    is_segment: bool,
+    is_remote: bool,
+    start_timestamp: f64,
+    timestamp: f64,
+    op: &'a str,
+    description: &'a str,
+    origin: &'a str,
+    status: &'static str,
+    data: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> SpanItem<'a> {
+    fn new(span: &'a Span) -> Self {
+        let tags = &*span.sentry_tags;
+
+        let mut data: BTreeMap<&str, &str> = BTreeMap::new();
+        data.insert("sentry.environment", &tags.environment);
+        data.insert("sentry.release", &tags.release);
+        data.insert("sentry.transaction", &tags.transaction);
+        data.insert("sentry.sdk.name", tags.sdk_name);
+        data.insert("thread.name", tags.thread_name);
+        data.insert("browser.name", tags.browser_name);
+        data.insert("user.email", &tags.user_email);
+        for (key, value) in &span.data {
+            data.insert(key, value);
+        }
+
+        Self {
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            parent_span_id: span.parent_span_id,
+            segment_id: span.segment_id,
+            is_segment: span.is_segment,
+            is_remote: span.is_remote,
+            start_timestamp: span.start_timestamp_precise,
+            timestamp: span.end_timestamp_precise,
+            op: tags.op,
+            description: &span.description,
+            origin: &span.origin,
+            status: "ok",
+            data,
+        }
+    }
+}
+
+/// Writes an envelope with one span item per span into `buffer`.
+///
+/// The envelope is written without a trailing newline.
+///
+/// All spans must belong to the same segment, since the envelope headers are derived from the
+/// first span.
+pub fn write_envelope(buffer: &mut Vec<u8>, relay_url: &str, spans: &[Span]) -> Result<()> {
+    let Some(first) = spans.first() else {
+        return Ok(());
+    };
+
+    let tags = &*first.sentry_tags;
+    let (major, minor, patch) = tags.sdk_version;
+
+    let headers = EnvelopeHeaders {
+        dsn: dsn(relay_url, first.project_id),
+        sdk: ClientSdk {
+            name: tags.sdk_name,
+            version: format!("{major}.{minor}.{patch}"),
+        },
+        trace: DynamicSamplingContext {
+            trace_id: first.trace_id,
+            public_key: public_key(first.project_id),
+            release: &tags.release,
+            environment: &tags.environment,
+            transaction: &tags.transaction,
+            sample_rate: "1",
+            sampled: "true",
+        },
+    };
+
+    serde_json::to_writer(&mut *buffer, &headers)?;
+
+    let mut payload = Vec::new();
+    for span in spans {
+        payload.clear();
+        serde_json::to_writer(&mut payload, &SpanItem::new(span))?;
+
+        let item_headers = ItemHeaders {
+            ty: "span",
+            length: payload.len(),
+            content_type: "application/json",
+        };
+
+        buffer.push(b'\n');
+        serde_json::to_writer(&mut *buffer, &item_headers)?;
+        buffer.push(b'\n');
+        buffer.write_all(&payload)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,468 @@
+//! Generates synthetic span workloads for Sentry's span ingestion pipeline.
+//!
+//! [`generate`] and [`replay`] run a workload into any [`Sink`]. To inspect spans before they are
+//! encoded, iterate a [`SpanStream`](stream::SpanStream) directly.
+
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+
+use crate::cli::Config;
+use crate::data::Stats;
+use crate::encoder::Encoder;
+use crate::manifest::ManifestWriter;
+use crate::progress::Progress;
+use crate::replay::Replayer;
+use crate::schema::SchemaValidator;
+use crate::stream::SpanStream;
+use crate::throttle::Throttle;
+
+pub use crate::producer::{MessageKey, Sink};
+
+pub mod cli;
+mod constants;
+pub mod data;
+pub mod distributions;
+pub mod encoder;
+mod envelope;
+pub mod manifest;
+pub mod metrics;
+mod otlp;
+pub mod producer;
+pub mod progress;
+pub mod replay;
+pub mod scheduler;
+pub mod schema;
+pub mod stream;
+pub mod throttle;
+pub mod types;
+
+/// The number of messages that workers can queue before they block on the sink.
+const WORKER_QUEUE_SIZE: usize = 1024;
+
+/// Generates the workload described by `config`, encodes it and writes all messages to `sink`.
+///
+/// With more than one worker, spans are generated and encoded on separate threads while the
+/// calling thread writes to the sink. Returns the number of generated spans along with the
+/// delivery outcomes of the sink. If `manifest` is set in the config, the manifest of all generated
+/// traces is written along the way.
+pub fn generate(config: &Config, sink: &mut dyn Sink) -> Result<Stats> {
+    log::info!(
+        "Generating spans with --seed {} --start-time {} --workers {}",
+        config.seed,
+        config.start_time,
+        config.workers
+    );
+
+    let manifest = config
+        .manifest
+        .as_deref()
+        .map(ManifestWriter::create)
+        .transpose()?;
+
+    let progress = Progress::new(config);
+    let mut stats = track_progress(config, &progress, || {
+        if config.workers > 1 {
+            generate_parallel(config, &progress, manifest.as_ref(), sink)
+        } else {
+            generate_worker(config, &progress, manifest.as_ref(), |key, payload| {
+                sink.produce(key, payload)?;
+                progress.record_sink(sink);
+                Ok(())
+            })
+        }
+    })?;
+
+    sink.flush()?;
+    sink.record_stats(&mut stats);
+    if let Some(manifest) = manifest {
+        manifest.flush()?;
+    }
+
+    Ok(stats)
+}
+
+/// Runs one worker per configured thread and writes their messages to `sink` in arrival order.
+fn generate_parallel(
+    config: &Config,
+    progress: &Progress,
+    manifest: Option<&ManifestWriter>,
+    sink: &mut dyn Sink,
+) -> Result<Stats> {
+    let (sender, receiver) = mpsc::sync_channel::<(MessageKey, Vec<u8>)>(WORKER_QUEUE_SIZE);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.workers)
+            .map(|index| {
+                let config = worker_config(config, index);
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    generate_worker(&config, progress, manifest, |key, payload| {
+                        sender
+                            .send((key, payload.to_vec()))
+                            .context("sink has stopped")
+                    })
+                })
+            })
+            .collect();
+
+        // The channel closes once all workers are done. If the sink fails, dropping the receiver
+        // stops the workers on their next message.
+        drop(sender);
+        for (key, payload) in receiver {
+            sink.produce(key, &payload)?;
+            progress.record_sink(sink);
+        }
+
+        let mut stats = Stats::default();
+        for worker in workers {
+            match worker.join() {
+                Ok(result) => stats.add(&result?),
+                Err(_) => anyhow::bail!("worker panicked"),
+            }
+        }
+
+        Ok(stats)
+    })
+}
+
+/// Generates and encodes the workload of a single worker and passes every message to `emit`.
+fn generate_worker<F>(
+    config: &Config,
+    progress: &Progress,
+    manifest: Option<&ManifestWriter>,
+    mut emit: F,
+) -> Result<Stats>
+where
+    F: FnMut(MessageKey, &[u8]) -> Result<()>,
+{
+    let mut stream = SpanStream::new(config)?;
+    let mut encoder = Encoder::new(config);
+    let mut validator = config.validate.then(SchemaValidator::new).transpose()?;
+
+    let start = Instant::now();
+    let (mut traces, mut segments, mut open_traces) = (0, 0, 0);
+    let mut messages = Stats::default();
+
+    while let Some(batch) = stream.next() {
+        if let Some(manifest) = manifest {
+            manifest.write(&stream.take_manifest())?;
+        }
+
+        let mut emitted = 0;
+        encoder.encode(&batch.spans, |spans, payload| {
+            // A message is due when the last span it contains is due.
+            emitted += spans.len();
+            if let Some(&due) = batch.due.get(emitted - 1) {
+                throttle::sleep_until(due);
+            }
+
+            if let Some(ref mut validator) = validator {
+                validator.check(payload);
+            }
+            emit(batch.key, payload)?;
+            messages.record_message(payload.len());
+            progress.record_message(spans.len(), payload.len());
+            Ok(())
+        })?;
+
+        let stats = stream.stats();
+        progress.record_generated(stats.traces - traces, stats.segments - segments);
+        progress.update_open_traces(open_traces, stream.open_traces());
+        (traces, segments, open_traces) = (stats.traces, stats.segments, stream.open_traces());
+    }
+
+    let mut stats = stream.stats().clone();
+    stats.add(&messages);
+    stats.invalid = validator.map_or(0, |validator| validator.invalid());
+    stats.target_rate = stream.target_rate();
+    stats.achieved_rate = stats.spans as f64 / start.elapsed().as_secs_f64();
+    Ok(stats)
+}
+
+/// Returns the share of `config` for the worker at `index`.
+///
+/// Count and concurrent traces are split evenly, with the remainder going to the first workers, and
+/// the throughput is divided by the number of workers. Every worker uses a different seed, so they
+/// generate disjoint traces.
+fn worker_config(config: &Config, index: usize) -> Config {
+    let share = |total: usize| total / config.workers + usize::from(index < total % config.workers);
+
+    Config {
+        count: config.count.map(share),
+        throughput: None,
+        throughput_schedule: config
+            .rate_schedule()
+            .map(|schedule| schedule.scaled(1.0 / config.workers as f64)),
+        concurrent_traces: share(config.concurrent_traces),
+        seed: worker_seed(config.seed, index),
+        workers: 1,
+        ..config.clone()
+    }
+}
+
+/// Derives the seed of the worker at `index` with SplitMix64, so that the workers of one seed do not
+/// repeat the workers of a neighboring seed.
+fn worker_seed(seed: u64, index: usize) -> u64 {
+    let mut z = seed.wrapping_add((index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Replays spans from a `snuba-spans` dump at `path` and writes them to `sink`.
+pub fn replay(config: &Config, path: &Path, sink: &mut dyn Sink) -> Result<Stats> {
+    log::info!(
+        "Replaying spans from {} with --seed {} --start-time {}",
+        path.display(),
+        config.seed,
+        config.start_time
+    );
+
+    let progress = Progress::new(config);
+    let mut stats = track_progress(config, &progress, || {
+        replay_spans(config, path, &progress, sink)
+    })?;
+
+    sink.flush()?;
+    sink.record_stats(&mut stats);
+    Ok(stats)
+}
+
+/// Writes replayed spans to `sink` until the count, the deadline or the end of the dump is
+/// reached.
+fn replay_spans(
+    config: &Config,
+    path: &Path,
+    progress: &Progress,
+    sink: &mut dyn Sink,
+) -> Result<Stats> {
+    let mut replayer = Replayer::new(path, config)?;
+    let mut throttle = Throttle::new(config);
+    let mut validator = config.validate.then(SchemaValidator::new).transpose()?;
+    let mut payload = Vec::new();
+    let mut messages = Stats::default();
+    let start = Instant::now();
+    let deadline = config.duration.map(|duration| start + duration.0);
+
+    while config
+        .count
+        .is_none_or(|count| replayer.stats().spans < count)
+        && deadline.is_none_or(|deadline| Instant::now() < deadline)
+    {
+        let Some(replayed) = replayer.next_span()? else {
+            break;
+        };
+
+        if let Some(due) = throttle.accept() {
+            throttle::sleep_until(due);
+        }
+
+        payload.clear();
+        serde_json::to_writer(&mut payload, &replayed.span)?;
+        if let Some(ref mut validator) = validator {
+            validator.check(&payload);
+        }
+        sink.produce(replayed.key, &payload)?;
+        messages.record_message(payload.len());
+        progress.record_message(1, payload.len());
+        progress.record_sink(sink);
+    }
+
+    let mut stats = replayer.stats().clone();
+    stats.add(&messages);
+    stats.invalid = validator.map_or(0, |validator| validator.invalid());
+    stats.target_rate = throttle.target_rate();
+    stats.achieved_rate = stats.spans as f64 / start.elapsed().as_secs_f64();
+    Ok(stats)
+}
+
+/// Runs `f` while logging the progress and serving metrics, if enabled in the config.
+fn track_progress<T, F>(config: &Config, progress: &Progress, f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let listener = match config.metrics_addr {
+        Some(addr) => Some(
+            TcpListener::bind(addr)
+                .with_context(|| format!("failed to serve metrics on {addr}"))?,
+        ),
+        None => None,
+    };
+
+    // Dropping the senders shuts down the reporter and the metrics server.
+    let (stop_reporter, reporter_stopped) = mpsc::channel::<()>();
+    let (stop_server, server_stopped) = mpsc::channel::<()>();
+
+    thread::scope(|scope| {
+        if let Some(interval) = config.progress_interval {
+            scope.spawn(move || progress.report(interval.0, &reporter_stopped));
+        }
+
+        if let Some(listener) = listener {
+            scope.spawn(move || metrics::serve(&listener, config, progress, &server_stopped));
+        }
+
+        let result = f();
+        drop((stop_reporter, stop_server));
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cli::OutputFormat;
+
+    /// Keeps all messages in memory.
+    #[derive(Default)]
+    struct MemorySink {
+        messages: Vec<(MessageKey, Vec<u8>)>,
+        flushed: bool,
+        /// Blocks once for the given time after the given number of messages.
+        stall: Option<(usize, Duration)>,
+    }
+
+    impl Sink for MemorySink {
+        fn produce(&mut self, key: MessageKey, payload: &[u8]) -> Result<()> {
+            if let Some((messages, duration)) = self.stall
+                && messages == self.messages.len()
+            {
+                thread::sleep(duration);
+            }
+
+            self.messages.push((key, payload.to_vec()));
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            count: Some(500),
+            batch_size: 3,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_generate_into_sink() {
+        let config = Config {
+            format: OutputFormat::OtlpJson,
+            ..config()
+        };
+
+        let mut sink = MemorySink::default();
+        let stats = generate(&config, &mut sink).unwrap();
+        assert!(sink.flushed);
+        assert!(stats.spans >= 500);
+        assert_eq!(stats.messages, sink.messages.len());
+
+        // Every message is one batch of a single segment.
+        let mut spans = 0;
+        for (key, payload) in &sink.messages {
+            let request: serde_json::Value = serde_json::from_slice(payload).unwrap();
+            let resources = request["resourceSpans"].as_array().unwrap();
+            assert_eq!(resources.len(), 1);
+
+            let batch = resources[0]["scopeSpans"][0]["spans"].as_array().unwrap();
+            assert!((1..=3).contains(&batch.len()));
+            for span in batch {
+                assert_eq!(span["traceId"], key.trace_id.to_string());
+            }
+            spans += batch.len();
+        }
+        assert_eq!(spans, stats.spans);
+    }
+
+    #[test]
+    fn test_span_stream() {
+        let config = config();
+        let mut stream = SpanStream::new(&config).unwrap();
+
+        let mut spans = 0;
+        for batch in stream.by_ref() {
+            assert!((1..=3).contains(&batch.spans.len()));
+            assert!(batch.due.is_empty());
+            for span in &batch.spans {
+                assert_eq!(span.trace_id, batch.key.trace_id);
+                assert_eq!(span.project_id, batch.key.project_id);
+                assert_eq!(span.segment_id, batch.spans[0].segment_id);
+            }
+            spans += batch.spans.len();
+        }
+
+        assert!(spans >= 500);
+        assert_eq!(spans, stream.stats().spans);
+        assert_eq!(stream.open_traces(), 0);
+    }
+
+    #[test]
+    fn test_worker_seeds() {
+        let seeds: std::collections::HashSet<_> = (0..16)
+            .flat_map(|seed| (0..16).map(move |index| worker_seed(seed, index)))
+            .collect();
+        assert_eq!(seeds.len(), 16 * 16);
+    }
+
+    /// Generates the workload of `config` and returns all messages as JSON lines.
+    fn output(config: &Config, stall: Option<(usize, Duration)>) -> Vec<u8> {
+        let mut sink = MemorySink {
+            stall,
+            ..MemorySink::default()
+        };
+        generate(config, &mut sink).unwrap();
+
+        let mut output = Vec::new();
+        for (_, payload) in sink.messages {
+            output.extend_from_slice(&payload);
+            output.push(b'\n');
+        }
+        output
+    }
+
+    #[test]
+    fn test_generate_reproducible() {
+        let config = Config {
+            seed: 42,
+            start_time: 1_700_000_000,
+            ..config()
+        };
+
+        assert_eq!(output(&config, None), output(&config, None));
+
+        let other = Config {
+            seed: 43,
+            ..config.clone()
+        };
+        assert_ne!(output(&config, None), output(&other, None));
+    }
+
+    #[test]
+    fn test_generate_reproducible_throttled() {
+        let config = Config {
+            seed: 42,
+            start_time: 1_700_000_000,
+            throughput: Some(5000),
+            throughput_jitter: true,
+            // Batch delays are waited for in real time when the throughput is limited.
+            batch_delay_ms: 1,
+            batch_delay_stddev: 0,
+            ..config()
+        };
+
+        // A sink that stalls for longer than the throttle makes up for must not change the data.
+        let stall = Some((100, Duration::from_millis(1500)));
+        assert_eq!(output(&config, None), output(&config, stall));
+    }
+}
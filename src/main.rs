@@ -1,119 +1,145 @@
-use std::io::{StdoutLock, Write};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
 
-use anyhow::Result;
-use rand::Rng;
-use rand::seq::IndexedRandom;
+use anyhow::{Context, Result};
 use serde::Serialize;
 
-use crate::cli::Config;
-use crate::data::RandomGenerator;
+use spangen::cli::{Args, Config};
+use spangen::data::Stats;
+use spangen::schema::SchemaValidator;
+use spangen::{manifest, producer};
 
-mod cli;
-mod constants;
-mod data;
-mod types;
-
-const MIN_SLEEP: Duration = Duration::from_millis(1);
+fn run(config: &Config) -> Result<()> {
+    let start = Instant::now();
+    let mut sink = producer::from_config(config)?;
 
-struct Throttle {
-    throughput: Option<u32>,
-    accepted: u32,
-    last_sleep: Instant,
-}
+    let stats = match config.replay {
+        Some(ref path) => spangen::replay(config, path, &mut *sink)?,
+        None => spangen::generate(config, &mut *sink)?,
+    };
 
-impl Throttle {
-    pub fn new(throughput: Option<u32>) -> Self {
-        Self {
-            throughput,
-            accepted: 0,
-            last_sleep: Instant::now(),
-        }
+    log::info!("Finished in {:?}", start.elapsed());
+    log::info!("  traces:   {}", stats.traces);
+    log::info!("  segments: {}", stats.segments);
+    log::info!("  spans:    {}", stats.spans);
+    log::info!("  errors:   {}", stats.delivery_errors);
+    log::info!("  rate:     {:.0}/s", stats.achieved_rate);
+    if stats.target_rate > 0.0 {
+        log::info!("  target:   {:.0}/s", stats.target_rate);
+    }
+    if config.http {
+        log::info!("  accepted: {}", stats.accepted);
+        log::info!("  rejected: {}", stats.rejected);
+        log::info!("  limited:  {}", stats.rate_limited);
     }
 
-    pub fn accept(&mut self) {
-        if self.throughput.is_some() {
-            self.accepted += 1;
-        }
+    if let Some(ref path) = config.summary_json {
+        write_summary(path, config, start.elapsed().as_secs_f64(), &stats)?;
     }
 
-    pub fn wait(&mut self) {
-        let Some(throughput) = self.throughput else {
-            return;
-        };
+    check_schema(config, &stats)
+}
 
-        let now = Instant::now();
-        let elapsed = now - self.last_sleep;
+/// The summary of a run that is written with `--summary-json`.
+#[derive(Serialize)]
+struct Summary<'a> {
+    /// The wall-clock time of the run in seconds.
+    elapsed_secs: f64,
+    #[serde(flatten)]
+    stats: &'a Stats,
+    config: &'a Config,
+}
 
-        let expected_duration = (Duration::from_secs(1) * self.accepted) / throughput;
-        let sleep_duration = expected_duration.saturating_sub(elapsed);
-        if sleep_duration >= MIN_SLEEP {
-            thread::sleep(sleep_duration);
-            self.last_sleep = now + sleep_duration;
-            self.accepted = 0;
-        }
+/// Writes the stats of the run along with the effective config to `path` as JSON.
+fn write_summary(path: &Path, config: &Config, elapsed_secs: f64, stats: &Stats) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    let summary = Summary {
+        elapsed_secs,
+        stats,
+        config,
+    };
+    serde_json::to_writer_pretty(&mut writer, &summary)?;
+    writeln!(writer)?;
+    writer.flush()?;
+
+    log::info!("Wrote summary to {}", path.display());
+    Ok(())
+}
+
+/// Fails the run if `--validate` is enabled and any messages violated the schema.
+fn check_schema(config: &Config, stats: &Stats) -> Result<()> {
+    if !config.validate {
+        return Ok(());
     }
+
+    log::info!("  invalid:  {}", stats.invalid);
+    if stats.invalid > 0 {
+        anyhow::bail!("{} messages violate the snuba-spans schema", stats.invalid);
+    }
+
+    Ok(())
 }
 
-struct StdoutProducer {
-    stdout: StdoutLock<'static>,
+/// Opens a JSONL file for reading, or stdin if `path` is `-`.
+fn open_input(path: &Path) -> Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(Box::new(BufReader::new(file)))
 }
 
-impl StdoutProducer {
-    pub fn new() -> Self {
-        Self {
-            stdout: std::io::stdout().lock(),
+/// Validates every line of a JSONL file against the schema and reports all violations.
+fn validate_file(path: &Path) -> Result<()> {
+    let reader = open_input(path)?;
+    let mut validator = SchemaValidator::new()?;
+    let mut messages = 0;
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        messages += 1;
+        for violation in validator.violations(line.as_bytes()) {
+            log::error!("line {}: {violation}", number + 1);
         }
     }
 
-    pub fn produce_json<T: Serialize>(&mut self, value: &T) -> Result<()> {
-        serde_json::to_writer(&mut self.stdout, value)?;
-        writeln!(&mut self.stdout)?;
-        Ok(())
+    if validator.invalid() > 0 {
+        anyhow::bail!(
+            "{} of {} messages violate the snuba-spans schema",
+            validator.invalid(),
+            messages
+        );
     }
+
+    log::info!("All {messages} messages are valid");
+    Ok(())
 }
 
-fn produce(config: &Config) -> Result<()> {
-    let start = Instant::now();
-    let mut generator = RandomGenerator::new(config);
-    let mut producer = StdoutProducer::new();
-    let mut throttle = Throttle::new(config.throughput);
-
-    while generator.stats().spans < config.count {
-        let trace = generator.trace();
-        let mut remote_parent = None;
-
-        for _ in 0..generator.segment_count() {
-            let segment = generator.segment(&trace);
-            let span_refs = generator.span_refs(&segment);
-
-            throttle.wait();
-
-            for span_ref in &span_refs {
-                let mut span = generator.span(&segment, *span_ref);
-                if span_ref.parent_id.is_none() {
-                    debug_assert!(span_ref.span_id == segment.span_id);
-                    span.parent_span_id = remote_parent;
-                    span.is_remote = remote_parent.is_some();
-                }
-
-                producer.produce_json(&span)?;
-                throttle.accept();
-            }
-
-            // in 50% of the cases, pick a random span as the remote parent for the next segment
-            if generator.rng().random_ratio(1, 2) {
-                remote_parent = span_refs.choose(generator.rng()).map(|sr| sr.span_id);
-            }
-        }
-    }
+/// Compares an export from a downstream store against the manifest of a previous run.
+fn verify_export(manifest: &Path, path: &Path) -> Result<()> {
+    let file = File::open(manifest)
+        .with_context(|| format!("failed to open manifest {}", manifest.display()))?;
+    let verification = manifest::verify(BufReader::new(file), open_input(path)?)?;
 
-    let stats = generator.stats();
-    log::info!("Finished in {:?}", start.elapsed());
-    log::info!("  traces:   {}", stats.traces);
-    log::info!("  segments: {}", stats.segments);
-    log::info!("  spans:    {}", stats.spans);
+    log::info!("Verified {} exported spans", verification.exported);
+    log::info!("  expected:   {}", verification.expected);
+    log::info!("  missing:    {}", verification.missing);
+    log::info!("  duplicated: {}", verification.duplicated);
+    log::info!("  extra:      {}", verification.extra);
+
+    if !verification.is_ok() {
+        anyhow::bail!("export does not match the manifest");
+    }
 
     Ok(())
 }
@@ -121,8 +147,22 @@ fn produce(config: &Config) -> Result<()> {
 fn main() -> Result<()> {
     pretty_env_logger::init();
 
-    let mut config: Config = argh::from_env();
+    let args: Args = argh::from_env();
+    let mut config = Config::load(&args)?;
     config.validate()?;
 
-    produce(&config)
+    if args.print_config {
+        print!("{}", toml::to_string(&config)?);
+        return Ok(());
+    }
+
+    if let Some(ref path) = config.validate_file {
+        return validate_file(path);
+    }
+
+    if let (Some(manifest), Some(path)) = (&config.manifest, &config.verify) {
+        return verify_export(manifest, path);
+    }
+
+    run(&config)
 }
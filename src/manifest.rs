@@ -0,0 +1,322 @@
+//! A manifest of the traces and segments of a run, and its verification against a downstream
+//! export.
+//!
+//! The manifest is a JSONL file with one line per trace. It lists the segments of the trace along
+//! with the number of spans emitted for each of them, so that the spans that arrive in a
+//! downstream store can be checked without keeping every span ID.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::scheduler::OpenTrace;
+use crate::types::{SpanId, TraceId};
+
+/// A trace in the manifest.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TraceManifest {
+    pub trace_id: TraceId,
+    pub segments: Vec<SegmentManifest>,
+}
+
+impl TraceManifest {
+    /// Lists the segments of a trace that has just been generated.
+    pub fn new(trace: &OpenTrace) -> Self {
+        let segments = trace
+            .segments
+            .iter()
+            .map(|segment| SegmentManifest {
+                span_id: segment.info.span_id,
+                project_id: segment.info.project_id,
+                spans: segment.span_refs.len(),
+                root: segment.span_refs.iter().any(|r| r.parent_id.is_none()),
+            })
+            .collect();
+
+        Self {
+            trace_id: trace.segments[0].info.trace.trace_id,
+            segments,
+        }
+    }
+}
+
+/// A segment in the manifest.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SegmentManifest {
+    /// The span ID of the segment's root span, which is the `segment_id` of all its spans.
+    pub span_id: SpanId,
+    pub project_id: u64,
+    /// The number of spans emitted for the segment, including the root span if present.
+    pub spans: usize,
+    /// Whether the root span of the segment was emitted.
+    pub root: bool,
+}
+
+/// Writes the manifest of a run, shared between all workers.
+pub struct ManifestWriter {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl ManifestWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create manifest {}", path.display()))?;
+
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Appends one line per trace to the manifest.
+    pub fn write(&self, traces: &[TraceManifest]) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for trace in traces {
+            serde_json::to_writer(&mut *writer, trace)?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+/// The outcome of comparing an export against a manifest.
+#[derive(Debug, Default)]
+pub struct Verification {
+    /// The number of spans listed in the manifest.
+    pub expected: usize,
+    /// The number of spans in the export.
+    pub exported: usize,
+    /// Spans of the manifest that are not in the export.
+    pub missing: usize,
+    /// Additional copies of spans that are in the export more than once.
+    pub duplicated: usize,
+    /// Spans in the export that are not in the manifest.
+    pub extra: usize,
+}
+
+impl Verification {
+    /// Returns `true` if the export contains exactly the spans of the manifest.
+    pub fn is_ok(&self) -> bool {
+        self.missing == 0 && self.duplicated == 0 && self.extra == 0
+    }
+}
+
+/// A span in the export of a downstream store.
+#[derive(Deserialize)]
+struct ExportedSpan {
+    /// The trace ID, either in hex or in UUID format.
+    trace_id: String,
+    span_id: SpanId,
+    segment_id: Option<SpanId>,
+}
+
+/// A segment of the manifest with the spans that were found in the export.
+struct ExpectedSegment {
+    trace_id: TraceId,
+    manifest: SegmentManifest,
+    seen: HashMap<SpanId, usize>,
+}
+
+/// Compares the spans in `export`, a JSONL file of snuba-spans messages, against `manifest` and logs
+/// every segment that does not match.
+///
+/// Spans are matched to segments by their trace ID and segment ID. A segment of the manifest
+/// matches if every expected span is in the export exactly once.
+pub fn verify(manifest: impl BufRead, export: impl BufRead) -> Result<Verification> {
+    let mut verification = Verification::default();
+    let mut segments = Vec::new();
+    let mut index = HashMap::new();
+
+    for (number, line) in manifest.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let trace: TraceManifest = serde_json::from_str(&line)
+            .with_context(|| format!("invalid manifest at line {}", number + 1))?;
+        for segment in trace.segments {
+            verification.expected += segment.spans;
+            index.insert((trace.trace_id, segment.span_id), segments.len());
+            segments.push(ExpectedSegment {
+                trace_id: trace.trace_id,
+                manifest: segment,
+                seen: HashMap::new(),
+            });
+        }
+    }
+
+    for (number, line) in export.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let span: ExportedSpan = serde_json::from_str(&line)
+            .with_context(|| format!("invalid span at line {}", number + 1))?;
+        let trace_id: TraceId = span
+            .trace_id
+            .replace('-', "")
+            .parse()
+            .with_context(|| format!("invalid trace_id at line {}", number + 1))?;
+        verification.exported += 1;
+
+        let segment = span
+            .segment_id
+            .and_then(|segment_id| index.get(&(trace_id, segment_id)));
+        match segment {
+            Some(&segment) => *segments[segment].seen.entry(span.span_id).or_default() += 1,
+            None => {
+                log::error!(
+                    "line {}: span {} of trace {trace_id} is not in the manifest",
+                    number + 1,
+                    span.span_id
+                );
+                verification.extra += 1;
+            }
+        }
+    }
+
+    for segment in &segments {
+        let expected = segment.manifest.spans;
+        let found = segment.seen.len();
+        let missing = expected.saturating_sub(found);
+        let extra = found.saturating_sub(expected);
+        let duplicated: usize = segment.seen.values().map(|count| count - 1).sum();
+
+        if missing > 0 || extra > 0 || duplicated > 0 {
+            log::error!(
+                "trace {} segment {} (project {}): {missing} of {expected} spans missing, \
+                 {duplicated} duplicated, {extra} extra",
+                segment.trace_id,
+                segment.manifest.span_id,
+                segment.manifest.project_id,
+            );
+        }
+
+        if segment.manifest.root && !segment.seen.contains_key(&segment.manifest.span_id) {
+            log::error!(
+                "trace {} segment {}: root span missing",
+                segment.trace_id,
+                segment.manifest.span_id
+            );
+        }
+
+        verification.missing += missing;
+        verification.extra += extra;
+        verification.duplicated += duplicated;
+    }
+
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const TRACE_ID: &str = "0123456789abcdef0123456789abcdef";
+    const SEGMENT_A: &str = "aaaaaaaaaaaaaaaa";
+    const SEGMENT_B: &str = "bbbbbbbbbbbbbbbb";
+
+    /// Returns a manifest with one trace of two segments, with three spans and one span.
+    fn manifest() -> String {
+        let trace = TraceManifest {
+            trace_id: TRACE_ID.parse().unwrap(),
+            segments: vec![
+                SegmentManifest {
+                    span_id: SEGMENT_A.parse().unwrap(),
+                    project_id: 1,
+                    spans: 3,
+                    root: true,
+                },
+                SegmentManifest {
+                    span_id: SEGMENT_B.parse().unwrap(),
+                    project_id: 2,
+                    spans: 1,
+                    root: false,
+                },
+            ],
+        };
+
+        serde_json::to_string(&trace).unwrap() + "\n"
+    }
+
+    fn span(trace_id: &str, span_id: &str, segment_id: &str) -> String {
+        format!(r#"{{"trace_id":"{trace_id}","span_id":"{span_id}","segment_id":"{segment_id}"}}"#)
+    }
+
+    fn verify_spans(spans: &[String]) -> Verification {
+        verify(Cursor::new(manifest()), Cursor::new(spans.join("\n"))).unwrap()
+    }
+
+    #[test]
+    fn test_verify_complete() {
+        let verification = verify_spans(&[
+            span(TRACE_ID, SEGMENT_A, SEGMENT_A),
+            span(TRACE_ID, "0000000000000001", SEGMENT_A),
+            span(TRACE_ID, "0000000000000002", SEGMENT_A),
+            span(TRACE_ID, "0000000000000003", SEGMENT_B),
+        ]);
+
+        assert!(verification.is_ok(), "{verification:?}");
+        assert_eq!(verification.expected, 4);
+        assert_eq!(verification.exported, 4);
+    }
+
+    #[test]
+    fn test_verify_uuid_trace_id() {
+        let uuid = "01234567-89ab-cdef-0123-456789abcdef";
+        let verification = verify_spans(&[span(uuid, SEGMENT_A, SEGMENT_A)]);
+
+        assert_eq!(verification.exported, 1);
+        assert_eq!(verification.missing, 3);
+        assert_eq!(verification.extra, 0);
+    }
+
+    #[test]
+    fn test_verify_mismatches() {
+        let verification = verify_spans(&[
+            // segment A: one span twice, one span missing
+            span(TRACE_ID, SEGMENT_A, SEGMENT_A),
+            span(TRACE_ID, SEGMENT_A, SEGMENT_A),
+            span(TRACE_ID, "0000000000000001", SEGMENT_A),
+            // segment B: two distinct spans instead of one
+            span(TRACE_ID, "0000000000000003", SEGMENT_B),
+            span(TRACE_ID, "0000000000000004", SEGMENT_B),
+            // unknown trace and unknown segment
+            span("ffffffffffffffffffffffffffffffff", SEGMENT_A, SEGMENT_A),
+            span(TRACE_ID, "0000000000000005", "cccccccccccccccc"),
+        ]);
+
+        assert!(!verification.is_ok());
+        assert_eq!(verification.expected, 4);
+        assert_eq!(verification.exported, 7);
+        assert_eq!(verification.missing, 1);
+        assert_eq!(verification.duplicated, 1);
+        assert_eq!(verification.extra, 3);
+    }
+
+    #[test]
+    fn test_verify_invalid_export() {
+        let error = verify(Cursor::new(manifest()), Cursor::new("\n{}\n")).unwrap_err();
+        assert_eq!(error.to_string(), "invalid span at line 2");
+    }
+
+    #[test]
+    fn test_verify_invalid_manifest() {
+        let error = verify(Cursor::new("{}\n"), Cursor::new("")).unwrap_err();
+        assert_eq!(error.to_string(), "invalid manifest at line 1");
+    }
+}
@@ -0,0 +1,169 @@
+//! A Prometheus endpoint that exposes the progress of a run.
+
+use std::fmt::{Display, Write as _};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::cli::{Config, RateSchedule};
+use crate::progress::Progress;
+
+/// The interval at which the listener is polled for new connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The maximum time to wait for a client to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves metrics at `/metrics` on `listener` until the sender of `stop` is dropped.
+pub fn serve(listener: &TcpListener, config: &Config, progress: &Progress, stop: &Receiver<()>) {
+    if let Err(error) = listener.set_nonblocking(true) {
+        log::error!("failed to serve metrics: {error}");
+        return;
+    }
+
+    if let Ok(addr) = listener.local_addr() {
+        log::info!("Serving metrics on http://{addr}/metrics");
+    }
+
+    let scraper = Scraper {
+        schedule: config.rate_schedule(),
+    };
+
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(POLL_INTERVAL) {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(error) = scraper.respond(stream, progress) {
+                        log::warn!("failed to respond to metrics request: {error}");
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    log::warn!("failed to accept metrics connection: {error}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Renders metrics for every request.
+///
+/// Scrapes do not change any state, so that any number of scrapers see consistent values. The
+/// achieved throughput is the `rate()` of `spangen_spans_total`.
+struct Scraper {
+    schedule: Option<RateSchedule>,
+}
+
+impl Scraper {
+    fn respond(&self, stream: TcpStream, progress: &Progress) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // Consume the headers, so that the client does not see a reset connection.
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render(progress)),
+            _ => ("404 Not Found", String::new()),
+        };
+
+        let mut stream = &stream;
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+
+    fn render(&self, progress: &Progress) -> String {
+        let counters = [
+            (
+                "spans_total",
+                "Spans written to the sink.",
+                progress.spans(),
+            ),
+            ("segments_total", "Segments started.", progress.segments()),
+            ("traces_total", "Traces started.", progress.traces()),
+            (
+                "bytes_total",
+                "Bytes of messages written to the sink.",
+                progress.bytes(),
+            ),
+            (
+                "delivery_errors_total",
+                "Messages that the sink failed to deliver.",
+                progress.delivery_errors(),
+            ),
+        ];
+
+        let mut gauges = vec![(
+            "open_traces",
+            "Traces that have been started but not fully emitted.",
+            progress.open_traces() as f64,
+        )];
+
+        if let Some(ref schedule) = self.schedule {
+            gauges.push((
+                "target_throughput",
+                "Spans per second of the throughput schedule.",
+                schedule.rate(progress.elapsed()),
+            ));
+        }
+
+        let mut out = String::new();
+        for (name, help, value) in counters {
+            metric(&mut out, name, "counter", help, value);
+        }
+        for (name, help, value) in gauges {
+            metric(&mut out, name, "gauge", help, value);
+        }
+
+        out
+    }
+}
+
+/// Writes a single metric with its metadata in the Prometheus text format.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    let _ = writeln!(out, "# HELP spangen_{name} {help}");
+    let _ = writeln!(out, "# TYPE spangen_{name} {kind}");
+    let _ = writeln!(out, "spangen_{name} {value}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrapes_are_independent() {
+        let config = Config {
+            throughput: Some(100),
+            ..Config::default()
+        };
+        let progress = Progress::new(&config);
+        progress.record_message(3, 300);
+
+        let scraper = Scraper {
+            schedule: config.rate_schedule(),
+        };
+        let first = scraper.render(&progress);
+        assert_eq!(scraper.render(&progress), first);
+
+        assert!(first.contains("spangen_spans_total 3\n"));
+        assert!(first.contains("spangen_target_throughput 100\n"));
+        assert!(!first.contains("NaN") && !first.contains("inf"));
+    }
+}
@@ -0,0 +1,155 @@
+//! Conversion of generated spans into OpenTelemetry traces.
+
+use std::collections::BTreeMap;
+
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::common::v1::{AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use opentelemetry_proto::tonic::trace::v1::span::SpanKind;
+use opentelemetry_proto::tonic::trace::v1::{self as trace, ResourceSpans, ScopeSpans, SpanFlags};
+
+use time::OffsetDateTime;
+
+use crate::data::Span;
+
+/// Builds an export request with one resource per project.
+pub fn export_request(spans: &[Span]) -> ExportTraceServiceRequest {
+    let mut projects = BTreeMap::<u64, Vec<&Span>>::new();
+    for span in spans {
+        projects.entry(span.project_id).or_default().push(span);
+    }
+
+    let resource_spans = projects
+        .into_values()
+        .map(|spans| ResourceSpans {
+            resource: Some(resource(spans[0])),
+            scope_spans: vec![ScopeSpans {
+                scope: Some(InstrumentationScope {
+                    name: env!("CARGO_PKG_NAME").to_owned(),
+                    version: env!("CARGO_PKG_VERSION").to_owned(),
+                    ..Default::default()
+                }),
+                spans: spans.into_iter().map(span).collect(),
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        })
+        .collect();
+
+    ExportTraceServiceRequest { resource_spans }
+}
+
+/// Describes the project and SDK that sent the span.
+fn resource(span: &Span) -> Resource {
+    let tags = &*span.sentry_tags;
+    let (major, minor, patch) = tags.sdk_version;
+
+    Resource {
+        attributes: vec![
+            string("service.name", format!("project-{}", span.project_id)),
+            string("service.version", &tags.release),
+            string("deployment.environment.name", &tags.environment),
+            string("telemetry.sdk.name", tags.sdk_name),
+            string("telemetry.sdk.version", format!("{major}.{minor}.{patch}")),
+            int("sentry.organization_id", span.organization_id),
+            int("sentry.project_id", span.project_id),
+        ],
+        dropped_attributes_count: 0,
+    }
+}
+
+fn span(span: &Span) -> trace::Span {
+    let is_segment = Some(span.span_id) == span.segment_id;
+
+    let mut flags = 0;
+    if span.parent_span_id.is_some() {
+        flags |= SpanFlags::ContextHasIsRemoteMask as u32;
+        if span.is_remote {
+            flags |= SpanFlags::ContextIsRemoteMask as u32;
+        }
+    }
+
+    let mut attributes = vec![
+        string("sentry.op", span.sentry_tags.op),
+        string("sentry.origin", &span.origin),
+        string("sentry.platform", span.sentry_tags.platform),
+        string("sentry.transaction", &span.sentry_tags.transaction),
+    ];
+    for (key, value) in &span.data {
+        attributes.push(string(key, value));
+    }
+
+    trace::Span {
+        trace_id: span.trace_id.as_bytes().to_vec(),
+        span_id: span.span_id.as_bytes().to_vec(),
+        parent_span_id: span
+            .parent_span_id
+            .map(|id| id.as_bytes().to_vec())
+            .unwrap_or_default(),
+        flags,
+        name: span.description.clone(),
+        kind: if is_segment {
+            SpanKind::Server
+        } else {
+            SpanKind::Internal
+        } as i32,
+        start_time_unix_nano: unix_nanos(span.start_timestamp),
+        end_time_unix_nano: unix_nanos(span.end_timestamp),
+        attributes,
+        ..Default::default()
+    }
+}
+
+fn string(key: &str, value: impl Into<String>) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.into())),
+        }),
+    }
+}
+
+fn int(key: &str, value: u64) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::IntValue(value as i64)),
+        }),
+    }
+}
+
+fn unix_nanos(timestamp: OffsetDateTime) -> u64 {
+    u64::try_from(timestamp.unix_timestamp_nanos()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Config;
+    use crate::data::RandomGenerator;
+    use crate::scheduler::OpenTrace;
+
+    #[test]
+    fn test_nanosecond_timestamps() {
+        let config = Config::default();
+        let mut generator = RandomGenerator::new(&config).unwrap();
+        let timestamp =
+            OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_456_789).unwrap();
+        let trace = OpenTrace::generate(&mut generator, timestamp);
+
+        for segment in &trace.segments {
+            for span_ref in &segment.span_refs {
+                let span = generator.span(&segment.info, *span_ref, segment.info.end_timestamp);
+                let otlp = self::span(&span);
+
+                let start = span_ref.start_timestamp.unix_timestamp_nanos() as u64;
+                assert_eq!(otlp.start_time_unix_nano, start);
+                assert_eq!(
+                    otlp.end_time_unix_nano - otlp.start_time_unix_nano,
+                    span_ref.duration.as_nanos() as u64
+                );
+            }
+        }
+    }
+}
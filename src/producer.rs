@@ -0,0 +1,576 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::Result;
+use rdkafka::ClientConfig;
+use rdkafka::client::ClientContext;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer as _, ProducerContext};
+
+use crate::cli::{Config, OutputFormat};
+use crate::data::Stats;
+use crate::envelope;
+use crate::types::TraceId;
+
+/// The maximum time to wait for outstanding messages when finishing a run.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The time to wait for deliveries when the producer queue is full.
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The initial backoff for retrying HTTP requests without a `Retry-After` header.
+const HTTP_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The maximum backoff for retrying HTTP requests.
+const HTTP_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Identifies the trace and project that a message belongs to.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageKey {
+    pub trace_id: TraceId,
+    pub project_id: u64,
+}
+
+/// A destination for encoded messages.
+pub trait Sink {
+    /// Writes an encoded message.
+    ///
+    /// The key is used for partitioning and routing if the sink supports it.
+    fn produce(&mut self, key: MessageKey, payload: &[u8]) -> Result<()>;
+
+    /// Waits for all outstanding messages to be written.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Adds the delivery outcomes of this sink to `stats`.
+    fn record_stats(&self, _stats: &mut Stats) {}
+}
+
+/// Creates the sink selected in the config.
+pub fn from_config(config: &Config) -> Result<Box<dyn Sink>> {
+    if config.http {
+        return Ok(Box::new(HttpProducer::new(config)));
+    }
+
+    Ok(match config.kafka_broker {
+        Some(ref broker) => Box::new(KafkaProducer::new(broker, config)?),
+        None => Box::new(StreamProducer::new(config)?),
+    })
+}
+
+/// Writes messages to stdout or a file.
+///
+/// Text messages are written as lines. Binary messages are prefixed with their length as varint.
+pub struct StreamProducer {
+    writer: Box<dyn Write>,
+    length_delimited: bool,
+}
+
+impl StreamProducer {
+    pub fn new(config: &Config) -> Result<Self> {
+        let writer: Box<dyn Write> = match config.output {
+            Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(std::io::stdout().lock()),
+        };
+
+        Ok(Self {
+            writer,
+            length_delimited: config.format == OutputFormat::OtlpProto,
+        })
+    }
+}
+
+impl Sink for StreamProducer {
+    fn produce(&mut self, _key: MessageKey, payload: &[u8]) -> Result<()> {
+        if self.length_delimited {
+            let mut prefix = Vec::with_capacity(10);
+            prost::encoding::encode_varint(payload.len() as u64, &mut prefix);
+            self.writer.write_all(&prefix)?;
+            self.writer.write_all(payload)?;
+        } else {
+            self.writer.write_all(payload)?;
+            writeln!(&mut self.writer)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Counts failed deliveries and logs only the first one as an error, so that an unavailable
+/// destination does not flood the output.
+#[derive(Default)]
+struct ErrorCounter(AtomicUsize);
+
+impl ErrorCounter {
+    fn record(&self, message: fmt::Arguments<'_>) {
+        if self.0.fetch_add(1, Ordering::Relaxed) == 0 {
+            log::error!("{message}");
+        } else {
+            log::debug!("{message}");
+        }
+    }
+
+    fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts failed deliveries reported by librdkafka.
+#[derive(Default)]
+struct DeliveryContext {
+    errors: ErrorCounter,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((error, _)) = delivery_result {
+            self.errors
+                .record(format_args!("failed to deliver message: {error}"));
+        }
+    }
+}
+
+/// Produces messages to a Kafka topic, keyed by trace ID.
+pub struct KafkaProducer {
+    producer: BaseProducer<DeliveryContext>,
+    topic: String,
+    partition: Option<i32>,
+}
+
+impl KafkaProducer {
+    /// Creates a producer connected to the given bootstrap servers.
+    pub fn new(brokers: &str, config: &Config) -> Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("compression.type", &config.kafka_compression)
+            .set("linger.ms", config.kafka_linger_ms.to_string())
+            .set("acks", &config.kafka_acks)
+            .create_with_context(DeliveryContext::default())?;
+
+        Ok(Self {
+            producer,
+            topic: config.kafka_topic.clone(),
+            partition: config.kafka_partition,
+        })
+    }
+}
+
+impl Sink for KafkaProducer {
+    fn produce(&mut self, key: MessageKey, payload: &[u8]) -> Result<()> {
+        let key = key.trace_id.to_string();
+        let mut record = BaseRecord::to(&self.topic).key(&key).payload(payload);
+
+        if let Some(partition) = self.partition {
+            record = record.partition(partition);
+        }
+
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => break,
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                    self.producer.poll(QUEUE_FULL_BACKOFF);
+                    record = rejected;
+                }
+                Err((error, _)) => return Err(error.into()),
+            }
+        }
+
+        // Serve delivery callbacks without blocking.
+        self.producer.poll(Duration::ZERO);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.producer.flush(FLUSH_TIMEOUT)?;
+        Ok(())
+    }
+
+    fn record_stats(&self, stats: &mut Stats) {
+        stats.delivery_errors += self.producer.context().errors.get();
+    }
+}
+
+/// Counts the outcomes of HTTP requests across all workers.
+#[derive(Default)]
+struct HttpCounters {
+    accepted: AtomicUsize,
+    rejected: AtomicUsize,
+    rate_limited: AtomicUsize,
+    errors: ErrorCounter,
+}
+
+struct HttpRequest {
+    url: String,
+    auth: String,
+    payload: Vec<u8>,
+}
+
+/// Sends envelopes to the envelope endpoint of a Relay.
+///
+/// Requests are sent by a pool of worker threads. Requests that are rate limited or fail with a
+/// server error are retried, honoring the `Retry-After` header if present.
+pub struct HttpProducer {
+    relay_url: String,
+    sender: Option<SyncSender<HttpRequest>>,
+    workers: Vec<JoinHandle<()>>,
+    counters: Arc<HttpCounters>,
+}
+
+impl HttpProducer {
+    pub fn new(config: &Config) -> Self {
+        let concurrency = config.http_concurrency;
+        let (sender, receiver) = std::sync::mpsc::sync_channel(concurrency * 2);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let counters = Arc::new(HttpCounters::default());
+
+        let workers = (0..concurrency)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let counters = Arc::clone(&counters);
+                let max_retries = config.http_max_retries;
+                thread::spawn(move || http_worker(&receiver, &counters, max_retries))
+            })
+            .collect();
+
+        Self {
+            relay_url: config.relay_url.trim_end_matches('/').to_owned(),
+            sender: Some(sender),
+            workers,
+            counters,
+        }
+    }
+}
+
+impl Sink for HttpProducer {
+    fn produce(&mut self, key: MessageKey, payload: &[u8]) -> Result<()> {
+        let request = HttpRequest {
+            url: format!("{}/api/{}/envelope/", self.relay_url, key.project_id),
+            auth: format!(
+                "Sentry sentry_version=7, sentry_key={}, sentry_client={}/{}",
+                envelope::public_key(key.project_id),
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+            ),
+            payload: payload.to_vec(),
+        };
+
+        let Some(ref sender) = self.sender else {
+            anyhow::bail!("HTTP producer has already been flushed");
+        };
+
+        sender.send(request)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // Closing the channel stops the workers once they have drained all pending requests.
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                anyhow::bail!("HTTP worker panicked");
+            }
+        }
+        Ok(())
+    }
+
+    fn record_stats(&self, stats: &mut Stats) {
+        stats.accepted += self.counters.accepted.load(Ordering::Relaxed);
+        stats.rejected += self.counters.rejected.load(Ordering::Relaxed);
+        stats.rate_limited += self.counters.rate_limited.load(Ordering::Relaxed);
+        stats.delivery_errors += self.counters.errors.get();
+    }
+}
+
+fn http_worker(receiver: &Mutex<Receiver<HttpRequest>>, counters: &HttpCounters, max_retries: u32) {
+    let agent = ureq::Agent::new();
+
+    loop {
+        // Release the lock before sending, so other workers can pick up requests concurrently.
+        let request = match receiver.lock().unwrap().recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+
+        let mut backoff = HTTP_INITIAL_BACKOFF;
+        for attempt in 0..=max_retries {
+            let result = agent
+                .post(&request.url)
+                .set("Content-Type", "application/x-sentry-envelope")
+                .set("X-Sentry-Auth", &request.auth)
+                .send_bytes(&request.payload);
+
+            let retry_after = match result {
+                Ok(_) => {
+                    counters.accepted.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(ureq::Error::Status(429, response)) => {
+                    counters.rate_limited.fetch_add(1, Ordering::Relaxed);
+                    retry_after(&response)
+                }
+                Err(ureq::Error::Status(status, response)) if status >= 500 => {
+                    log::debug!("envelope request failed with status {status}");
+                    retry_after(&response)
+                }
+                Err(ureq::Error::Status(status, _)) => {
+                    log::debug!("envelope rejected with status {status}");
+                    counters.rejected.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(ureq::Error::Transport(error)) => {
+                    log::debug!("envelope request failed: {error}");
+                    None
+                }
+            };
+
+            if attempt == max_retries {
+                counters.errors.record(format_args!(
+                    "failed to send envelope after {} attempts",
+                    attempt + 1
+                ));
+                break;
+            }
+
+            thread::sleep(retry_after.unwrap_or(backoff));
+            backoff = (backoff * 2).min(HTTP_MAX_BACKOFF);
+        }
+    }
+}
+
+/// Parses the `Retry-After` header in seconds.
+fn retry_after(response: &ureq::Response) -> Option<Duration> {
+    let seconds = response.header("Retry-After")?.trim().parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::types::{RDKafkaApiKey, RDKafkaRespErr};
+    use rdkafka::{Message, Offset, TopicPartitionList};
+
+    use super::*;
+    use crate::cli::KAFKA_COMPRESSION_CODECS;
+
+    const TOPIC: &str = "snuba-spans";
+
+    fn kafka_config() -> Config {
+        Config {
+            kafka_topic: TOPIC.to_owned(),
+            kafka_acks: "1".to_owned(),
+            ..Config::default()
+        }
+    }
+
+    fn key(trace_id: &str) -> MessageKey {
+        MessageKey {
+            trace_id: trace_id.parse().unwrap(),
+            project_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_kafka_keys_by_trace_id() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic(TOPIC, 4, 1).unwrap();
+
+        let trace_ids = [
+            "0123456789abcdef0123456789abcdef",
+            "fedcba9876543210fedcba9876543210",
+        ];
+
+        let mut producer =
+            KafkaProducer::new(&cluster.bootstrap_servers(), &kafka_config()).unwrap();
+        for trace_id in trace_ids {
+            producer
+                .produce(key(trace_id), trace_id.as_bytes())
+                .unwrap();
+        }
+        producer.flush().unwrap();
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "spangen-test")
+            .create()
+            .unwrap();
+
+        let mut assignment = TopicPartitionList::new();
+        for partition in 0..4 {
+            assignment
+                .add_partition_offset(TOPIC, partition, Offset::Beginning)
+                .unwrap();
+        }
+        consumer.assign(&assignment).unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < trace_ids.len() {
+            let message = consumer
+                .poll(Duration::from_secs(10))
+                .expect("timed out waiting for messages")
+                .unwrap();
+            received.push((
+                message.key().unwrap().to_vec(),
+                message.payload().unwrap().to_vec(),
+            ));
+        }
+
+        received.sort();
+        for ((key, payload), trace_id) in received.into_iter().zip(trace_ids) {
+            assert_eq!(key, trace_id.as_bytes());
+            assert_eq!(payload, trace_id.as_bytes());
+        }
+
+        let mut stats = Stats::default();
+        producer.record_stats(&mut stats);
+        assert_eq!(stats.delivery_errors, 0);
+    }
+
+    #[test]
+    fn test_kafka_compression_codecs() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic(TOPIC, 1, 1).unwrap();
+
+        for codec in KAFKA_COMPRESSION_CODECS {
+            let config = Config {
+                kafka_compression: codec.to_string(),
+                ..kafka_config()
+            };
+
+            let mut producer = KafkaProducer::new(&cluster.bootstrap_servers(), &config).unwrap();
+            producer
+                .produce(key("0123456789abcdef0123456789abcdef"), codec.as_bytes())
+                .unwrap();
+            producer.flush().unwrap();
+
+            let mut stats = Stats::default();
+            producer.record_stats(&mut stats);
+            assert_eq!(stats.delivery_errors, 0, "{codec}");
+        }
+    }
+
+    #[test]
+    fn test_kafka_delivery_errors() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic(TOPIC, 1, 1).unwrap();
+        cluster.request_errors(
+            RDKafkaApiKey::Produce,
+            &[RDKafkaRespErr::RD_KAFKA_RESP_ERR_MSG_SIZE_TOO_LARGE; 2],
+        );
+
+        let mut producer =
+            KafkaProducer::new(&cluster.bootstrap_servers(), &kafka_config()).unwrap();
+        producer
+            .produce(key("0123456789abcdef0123456789abcdef"), b"first")
+            .unwrap();
+        producer.flush().unwrap();
+        producer
+            .produce(key("fedcba9876543210fedcba9876543210"), b"second")
+            .unwrap();
+        producer.flush().unwrap();
+
+        let mut stats = Stats::default();
+        producer.record_stats(&mut stats);
+        assert_eq!(stats.delivery_errors, 2);
+    }
+
+    /// Serves the given responses to consecutive requests and counts the requests.
+    fn serve_http(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&requests);
+        thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+
+                let mut content_length = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                counter.fetch_add(1, Ordering::Relaxed);
+
+                let mut stream = &stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 {response}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[test]
+    fn test_http_outcomes() {
+        let (relay_url, requests) = serve_http(vec![
+            // accepted
+            "200 OK",
+            // rate limited, then accepted on the first retry
+            "429 Too Many Requests\r\nRetry-After: 0",
+            "200 OK",
+            // server error on every attempt
+            "503 Service Unavailable",
+            "503 Service Unavailable",
+            "503 Service Unavailable",
+            // rejected without retries
+            "400 Bad Request",
+        ]);
+
+        let config = Config {
+            relay_url,
+            http: true,
+            http_concurrency: 1,
+            http_max_retries: 2,
+            ..Config::default()
+        };
+
+        let mut producer = HttpProducer::new(&config);
+        for _ in 0..4 {
+            producer
+                .produce(key("0123456789abcdef0123456789abcdef"), b"{}")
+                .unwrap();
+        }
+        producer.flush().unwrap();
+
+        let mut stats = Stats::default();
+        producer.record_stats(&mut stats);
+        assert_eq!(stats.accepted, 2);
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(stats.rate_limited, 1);
+        assert_eq!(stats.delivery_errors, 1);
+
+        // Four envelopes with one retry after the 429 and two retries after the 503s.
+        assert_eq!(requests.load(Ordering::Relaxed), 7);
+    }
+}
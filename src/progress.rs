@@ -0,0 +1,161 @@
+//! Live progress of a run, shared between workers, the progress reporter and the metrics server.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::cli::Config;
+use crate::data::Stats;
+use crate::producer::Sink;
+
+/// Counters that workers update while spans are emitted.
+pub struct Progress {
+    start: Instant,
+    count: Option<usize>,
+    deadline: Option<Instant>,
+    spans: AtomicUsize,
+    bytes: AtomicUsize,
+    traces: AtomicUsize,
+    segments: AtomicUsize,
+    open_traces: AtomicUsize,
+    delivery_errors: AtomicUsize,
+}
+
+impl Progress {
+    pub fn new(config: &Config) -> Self {
+        let start = Instant::now();
+
+        Self {
+            start,
+            count: config.count,
+            deadline: config.duration.map(|duration| start + duration.0),
+            spans: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            traces: AtomicUsize::new(0),
+            segments: AtomicUsize::new(0),
+            open_traces: AtomicUsize::new(0),
+            delivery_errors: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records a message with `spans` spans that was written to the sink.
+    pub fn record_message(&self, spans: usize, bytes: usize) {
+        self.spans.fetch_add(spans, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Records traces and segments that a worker has started.
+    pub fn record_generated(&self, traces: usize, segments: usize) {
+        self.traces.fetch_add(traces, Ordering::Relaxed);
+        self.segments.fetch_add(segments, Ordering::Relaxed);
+    }
+
+    /// Updates the delivery errors from the current outcomes of `sink`.
+    pub fn record_sink(&self, sink: &dyn Sink) {
+        let mut stats = Stats::default();
+        sink.record_stats(&mut stats);
+        self.delivery_errors
+            .store(stats.delivery_errors, Ordering::Relaxed);
+    }
+
+    /// Updates the open traces of a single worker from `previous` to `current`.
+    pub fn update_open_traces(&self, previous: usize, current: usize) {
+        if current > previous {
+            self.open_traces
+                .fetch_add(current - previous, Ordering::Relaxed);
+        } else {
+            self.open_traces
+                .fetch_sub(previous - current, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of spans written to the sink so far.
+    pub fn spans(&self) -> usize {
+        self.spans.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes written to the sink so far.
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of traces started so far.
+    pub fn traces(&self) -> usize {
+        self.traces.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of segments started so far.
+    pub fn segments(&self) -> usize {
+        self.segments.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages that the sink failed to deliver so far.
+    pub fn delivery_errors(&self) -> usize {
+        self.delivery_errors.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of traces that have been started but not fully emitted.
+    pub fn open_traces(&self) -> usize {
+        self.open_traces.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time since the start of the run.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Estimates the time until `count` spans are emitted or the deadline is reached, whichever
+    /// comes first.
+    ///
+    /// The estimate for `count` assumes that the average rate so far remains the same.
+    pub fn remaining(&self) -> Option<Duration> {
+        let elapsed = self.elapsed();
+        let spans = self.spans();
+
+        let until_count = self.count.and_then(|count| {
+            let rate = spans as f64 / elapsed.as_secs_f64();
+            (rate > 0.0).then(|| Duration::from_secs_f64(count.saturating_sub(spans) as f64 / rate))
+        });
+        let until_deadline = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        match (until_count, until_deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Logs a progress line every `interval` until the sender of `stop` is dropped.
+    pub fn report(&self, interval: Duration, stop: &Receiver<()>) {
+        let mut last_time = self.start;
+        let mut last_spans = 0;
+        let mut last_bytes = 0;
+
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            let now = Instant::now();
+            let spans = self.spans();
+            let bytes = self.bytes();
+            let seconds = (now - last_time).as_secs_f64();
+
+            log::info!(
+                "{} elapsed, {spans} spans, {:.0} spans/s, {:.2} MB/s, {} traces open, {} remaining",
+                format_duration(now - self.start),
+                (spans - last_spans) as f64 / seconds,
+                (bytes - last_bytes) as f64 / seconds / 1_000_000.0,
+                self.open_traces(),
+                self.remaining()
+                    .map_or_else(|| String::from("unknown"), format_duration),
+            );
+
+            last_time = now;
+            last_spans = spans;
+            last_bytes = bytes;
+        }
+    }
+}
+
+/// Formats a duration rounded to whole seconds.
+fn format_duration(duration: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string()
+}
@@ -0,0 +1,422 @@
+//! Replay of spans from a `snuba-spans` JSONL dump.
+
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{Map, Value};
+
+use crate::cli::Config;
+use crate::data::Stats;
+use crate::producer::MessageKey;
+use crate::types::{SpanId, TraceId};
+
+/// The magic bytes at the start of every gzip file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Fields of a span that hold span IDs.
+const SPAN_ID_FIELDS: [&str; 3] = ["span_id", "parent_span_id", "segment_id"];
+
+/// Fields of a span that may contain other IDs. All strings in them that have the shape of an ID
+/// are rewritten.
+const OTHER_ID_FIELDS: [&str; 5] = ["profile_id", "event_id", "data", "sentry_tags", "tags"];
+
+/// Fields of a span that hold timestamps in seconds.
+const TIMESTAMP_FIELDS: [&str; 3] = [
+    "start_timestamp_precise",
+    "end_timestamp_precise",
+    "received",
+];
+
+/// A span read from the dump with rewritten IDs and timestamps.
+pub struct ReplayedSpan {
+    pub key: MessageKey,
+    pub span: Map<String, Value>,
+}
+
+/// Reads spans from a dump and rewrites them so they can be emitted again.
+///
+/// Trace and span IDs are replaced with IDs derived from a random salt, so that the tree
+/// structure is preserved without emitting the original IDs. The same applies to profile and event
+/// IDs and to IDs in `data`, `sentry_tags` and `tags`, which keep their format. Trace IDs may be
+/// in hex or UUID format and are always emitted in hex. Timestamps are shifted so that the
+/// first span starts at the configured start time, preserving all relative timing. When looping,
+/// every pass uses a new salt and continues where the timeline of the previous pass ended.
+pub struct Replayer {
+    path: PathBuf,
+    repeat: bool,
+    reader: Box<dyn BufRead>,
+    line: String,
+    line_number: usize,
+    rng: StdRng,
+    salt: u64,
+    start_time: f64,
+    /// The original timestamp of the first span in the dump.
+    anchor: Option<f64>,
+    /// The offset of the current pass from the start time in seconds.
+    pass_offset: f64,
+    /// The latest original timestamp seen in the current pass.
+    pass_end: f64,
+    /// The number of spans read in the current pass.
+    pass_spans: usize,
+    stats: Stats,
+}
+
+impl Replayer {
+    pub fn new(path: &Path, config: &Config) -> Result<Self> {
+        Ok(Self::from_reader(path, open(path)?, config))
+    }
+
+    /// Creates a replayer that reads the first pass from `reader` and reopens `path` when looping.
+    fn from_reader(path: &Path, reader: Box<dyn BufRead>, config: &Config) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        Self {
+            path: path.to_owned(),
+            repeat: config.replay_loop,
+            reader,
+            line: String::new(),
+            line_number: 0,
+            salt: rng.random(),
+            rng,
+            start_time: config.start_time as f64,
+            anchor: None,
+            pass_offset: 0.0,
+            pass_end: f64::MIN,
+            pass_spans: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Returns the next span, or `None` if the dump is exhausted and looping is disabled.
+    pub fn next_span(&mut self) -> Result<Option<ReplayedSpan>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                if !self.repeat || self.pass_spans == 0 {
+                    return Ok(None);
+                }
+                self.restart()?;
+                continue;
+            }
+
+            self.line_number += 1;
+            if self.line.trim().is_empty() {
+                continue;
+            }
+
+            let span = serde_json::from_str(&self.line)
+                .with_context(|| format!("invalid span on line {}", self.line_number))?;
+            let span = self
+                .rewrite(span)
+                .with_context(|| format!("invalid span on line {}", self.line_number))?;
+
+            return Ok(Some(span));
+        }
+    }
+
+    /// Starts the next pass over the dump.
+    fn restart(&mut self) -> Result<()> {
+        if let Some(anchor) = self.anchor {
+            self.pass_offset += (self.pass_end - anchor).max(0.0);
+        }
+
+        self.reader = open(&self.path)?;
+        self.line_number = 0;
+        self.salt = self.rng.random();
+        self.pass_end = f64::MIN;
+        self.pass_spans = 0;
+        Ok(())
+    }
+
+    fn rewrite(&mut self, mut span: Map<String, Value>) -> Result<ReplayedSpan> {
+        let trace_id = match span.get("trace_id").and_then(Value::as_str) {
+            Some(trace_id) => self.trace_id(parse_trace_id(trace_id)?),
+            None => anyhow::bail!("missing trace_id"),
+        };
+        span.insert("trace_id".to_owned(), trace_id.to_string().into());
+
+        for field in SPAN_ID_FIELDS {
+            if let Some(span_id) = span.get(field).and_then(Value::as_str) {
+                let span_id = self.span_id(span_id.parse()?);
+                span.insert(field.to_owned(), span_id.to_string().into());
+            }
+        }
+
+        for field in OTHER_ID_FIELDS {
+            if let Some(value) = span.get_mut(field) {
+                self.rewrite_ids(value);
+            }
+        }
+
+        let start = span
+            .get("start_timestamp_precise")
+            .and_then(Value::as_f64)
+            .context("missing start_timestamp_precise")?;
+        let anchor = *self.anchor.get_or_insert(start);
+        let shift = self.start_time + self.pass_offset - anchor;
+
+        for field in TIMESTAMP_FIELDS {
+            if let Some(timestamp) = span.get(field).and_then(Value::as_f64) {
+                self.pass_end = self.pass_end.max(timestamp);
+                span.insert(field.to_owned(), (timestamp + shift).into());
+            }
+        }
+
+        if let Some(timestamp_ms) = span.get("start_timestamp_ms").and_then(Value::as_f64) {
+            let shifted = (timestamp_ms + shift * 1000.0).round() as u64;
+            span.insert("start_timestamp_ms".to_owned(), shifted.into());
+        }
+
+        let project_id = span
+            .get("project_id")
+            .and_then(Value::as_u64)
+            .context("missing project_id")?;
+
+        self.pass_spans += 1;
+        self.stats.spans += 1;
+        *self.stats.projects.entry(project_id).or_default() += 1;
+        if let Some(organization_id) = span.get("organization_id").and_then(Value::as_u64) {
+            *self.stats.organizations.entry(organization_id).or_default() += 1;
+        }
+        let span_id = span.get("span_id");
+        if span_id.is_some() && span_id == span.get("segment_id") {
+            self.stats.segments += 1;
+            if span.get("parent_span_id").is_none_or(Value::is_null) {
+                self.stats.traces += 1;
+            }
+        }
+
+        Ok(ReplayedSpan {
+            key: MessageKey {
+                trace_id,
+                project_id,
+            },
+            span,
+        })
+    }
+
+    /// Rewrites all strings in `value` that have the shape of a span ID, a trace ID or a UUID.
+    fn rewrite_ids(&self, value: &mut Value) {
+        match value {
+            Value::String(string) => {
+                if let Some(id) = self.rewrite_id(string) {
+                    *string = id;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.rewrite_ids(value)),
+            Value::Object(map) => map.values_mut().for_each(|value| self.rewrite_ids(value)),
+            _ => (),
+        }
+    }
+
+    fn rewrite_id(&self, id: &str) -> Option<String> {
+        match id.len() {
+            16 => Some(self.span_id(id.parse().ok()?).to_string()),
+            32 => Some(self.trace_id(id.parse().ok()?).to_string()),
+            36 => Some(uuid(self.trace_id(parse_trace_id(id).ok()?))),
+            _ => None,
+        }
+    }
+
+    fn trace_id(&self, trace_id: TraceId) -> TraceId {
+        let high = self.hash(trace_id.as_bytes(), 0).to_be_bytes();
+        let low = self.hash(trace_id.as_bytes(), 1).to_be_bytes();
+
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&high);
+        bytes[8..].copy_from_slice(&low);
+        TraceId::from_bytes(bytes)
+    }
+
+    fn span_id(&self, span_id: SpanId) -> SpanId {
+        SpanId::from_bytes(self.hash(span_id.as_bytes(), 0).to_be_bytes())
+    }
+
+    fn hash(&self, bytes: &[u8], index: u8) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.salt, index, bytes).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Parses a trace ID in hex or UUID format.
+fn parse_trace_id(trace_id: &str) -> Result<TraceId> {
+    Ok(trace_id.replace('-', "").parse()?)
+}
+
+/// Formats a trace ID as a UUID.
+fn uuid(trace_id: TraceId) -> String {
+    let hex = trace_id.to_string();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Opens a dump, decompressing it if it is gzipped.
+fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+    );
+
+    let reader: Box<dyn BufRead> = if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    };
+
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const TRACE_ID: &str = "0123456789abcdef0123456789abcdef";
+    const TRACE_UUID: &str = "01234567-89ab-cdef-0123-456789abcdef";
+    const ROOT_ID: &str = "aaaaaaaaaaaaaaaa";
+    const CHILD_ID: &str = "bbbbbbbbbbbbbbbb";
+    const PROFILE_ID: &str = "fedcba98-7654-3210-fedc-ba9876543210";
+    const EVENT_ID: &str = "ffffffffffffffffffffffffffffffff";
+
+    fn dump() -> String {
+        let root = serde_json::json!({
+            "trace_id": TRACE_ID,
+            "span_id": ROOT_ID,
+            "segment_id": ROOT_ID,
+            "parent_span_id": null,
+            "profile_id": PROFILE_ID,
+            "event_id": EVENT_ID,
+            "project_id": 1,
+            "start_timestamp_precise": 1000.5,
+            "end_timestamp_precise": 1003.5,
+            "start_timestamp_ms": 1000500,
+            "received": 1004.0,
+            "data": { "sentry.segment.id": ROOT_ID, "count": 3 },
+            "sentry_tags": { "trace.id": TRACE_ID, "user.id": "42" },
+        });
+        let child = serde_json::json!({
+            "trace_id": TRACE_UUID,
+            "span_id": CHILD_ID,
+            "segment_id": ROOT_ID,
+            "parent_span_id": ROOT_ID,
+            "profile_id": PROFILE_ID,
+            "project_id": 1,
+            "start_timestamp_precise": 1002.5,
+            "end_timestamp_precise": 1003.0,
+            "start_timestamp_ms": 1002500,
+        });
+
+        format!("{root}\n\n{child}\n")
+    }
+
+    fn replay(seed: u64) -> Vec<ReplayedSpan> {
+        let config = Config {
+            seed,
+            start_time: 2000,
+            ..Config::default()
+        };
+
+        let reader = Box::new(Cursor::new(dump()));
+        let mut replayer = Replayer::from_reader(Path::new("dump.jsonl"), reader, &config);
+        let mut spans = Vec::new();
+        while let Some(span) = replayer.next_span().unwrap() {
+            spans.push(span);
+        }
+
+        assert_eq!(replayer.stats().spans, 2);
+        assert_eq!(replayer.stats().segments, 1);
+        assert_eq!(replayer.stats().traces, 1);
+        spans
+    }
+
+    #[test]
+    fn test_rewrite_ids() {
+        let spans = replay(42);
+        let (root, child) = (&spans[0].span, &spans[1].span);
+
+        // The same IDs are rewritten consistently, including UUID trace IDs.
+        let trace_id = spans[0].key.trace_id.to_string();
+        assert_eq!(root["trace_id"], trace_id);
+        assert_eq!(child["trace_id"], trace_id);
+        assert_eq!(spans[1].key.trace_id, spans[0].key.trace_id);
+        assert_eq!(root["sentry_tags"]["trace.id"], trace_id);
+
+        assert_eq!(root["segment_id"], root["span_id"]);
+        assert_eq!(child["segment_id"], root["span_id"]);
+        assert_eq!(child["parent_span_id"], root["span_id"]);
+        assert_eq!(root["data"]["sentry.segment.id"], root["span_id"]);
+        assert!(root["parent_span_id"].is_null());
+
+        assert_eq!(child["profile_id"], root["profile_id"]);
+        let profile_id = root["profile_id"].as_str().unwrap();
+        assert_eq!(profile_id.len(), PROFILE_ID.len());
+        assert_eq!(root["event_id"].as_str().unwrap().len(), EVENT_ID.len());
+
+        // Values that are not IDs are kept.
+        assert_eq!(root["data"]["count"], 3);
+        assert_eq!(root["sentry_tags"]["user.id"], "42");
+
+        // None of the original IDs are emitted.
+        for span in &spans {
+            let json = serde_json::to_string(&span.span).unwrap();
+            for id in [
+                TRACE_ID, TRACE_UUID, ROOT_ID, CHILD_ID, PROFILE_ID, EVENT_ID,
+            ] {
+                assert!(!json.contains(id), "{id} in {json}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewrite_ids_seeded() {
+        let ids = |seed| {
+            replay(seed)
+                .iter()
+                .map(|span| span.span["span_id"].clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(42), ids(42));
+        assert_ne!(ids(42), ids(43));
+    }
+
+    #[test]
+    fn test_shift_timestamps() {
+        let spans = replay(42);
+        let (root, child) = (&spans[0].span, &spans[1].span);
+
+        // The first span starts at the start time and relative timing is preserved.
+        assert_eq!(root["start_timestamp_precise"], 2000.0);
+        assert_eq!(root["end_timestamp_precise"], 2003.0);
+        assert_eq!(root["received"], 2003.5);
+        assert_eq!(root["start_timestamp_ms"], 2_000_000);
+        assert_eq!(child["start_timestamp_precise"], 2002.0);
+        assert_eq!(child["start_timestamp_ms"], 2_002_000);
+    }
+
+    #[test]
+    fn test_invalid_trace_id() {
+        let config = Config::default();
+        let reader = Box::new(Cursor::new(r#"{"trace_id":"not-a-trace-id"}"#));
+        let mut replayer = Replayer::from_reader(Path::new("dump.jsonl"), reader, &config);
+        let error = replayer.next_span().err().unwrap();
+        assert_eq!(error.to_string(), "invalid span on line 1");
+    }
+}
@@ -0,0 +1,193 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::ops::Range;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use time::OffsetDateTime;
+
+use crate::data::{RandomGenerator, SegmentInfo, SpanRef};
+use crate::types::SpanId;
+
+/// A segment whose span tree has been generated but not fully emitted yet.
+pub struct OpenSegment {
+    pub info: SegmentInfo,
+    pub span_refs: Vec<SpanRef>,
+    /// The span in a previous segment that the root of this segment continues from.
+    pub remote_parent: Option<SpanId>,
+    /// The time after the end of the segment at which the next batch is flushed.
+    pub flush_delay: Duration,
+    emitted: usize,
+}
+
+impl OpenSegment {
+    /// Returns the range of the next batch of at most `batch_size` spans and marks it as emitted.
+    pub fn next_batch(&mut self, batch_size: usize) -> Range<usize> {
+        let start = self.emitted;
+        self.emitted = (start + batch_size).min(self.span_refs.len());
+        start..self.emitted
+    }
+
+    /// Returns `true` if all spans of this segment have been emitted.
+    pub fn is_done(&self) -> bool {
+        self.emitted >= self.span_refs.len()
+    }
+}
+
+/// A trace with segments that are still pending emission.
+///
+/// Segments of a trace are emitted one after another, each of them in one or more batches.
+pub struct OpenTrace {
+    pub segments: VecDeque<OpenSegment>,
+}
+
+impl OpenTrace {
+    /// Generates a new trace starting at `timestamp` with all of its segments and span trees.
+    pub fn generate(generator: &mut RandomGenerator, timestamp: OffsetDateTime) -> Self {
+        let info = generator.trace(timestamp);
+        let segment_count = generator.segment_count();
+
+        let mut segments = VecDeque::with_capacity(segment_count);
+        let mut remote_parent = None;
+
+        for _ in 0..segment_count {
+            let segment = generator.segment(&info, remote_parent.as_ref());
+            let span_refs = generator.span_refs(&segment);
+            let segment_parent = remote_parent.map(|sr| sr.span_id);
+
+            // in 50% of the cases, pick a random span as the remote parent for the next segment
+            if generator.rng().random_ratio(1, 2) {
+                remote_parent = span_refs.choose(generator.rng()).copied();
+            }
+
+            segments.push_back(OpenSegment {
+                info: segment,
+                span_refs,
+                remote_parent: segment_parent,
+                flush_delay: Duration::ZERO,
+                emitted: 0,
+            });
+        }
+
+        Self { segments }
+    }
+
+    /// Returns the duration of the trace's first segment, which is not nested into a remote parent.
+    pub fn duration(&self) -> Duration {
+        let root = &self.segments[0].info;
+        (root.end_timestamp - root.start_timestamp).unsigned_abs()
+    }
+
+    /// Returns the total number of spans in this trace, including those already emitted.
+    pub fn span_count(&self) -> usize {
+        self.segments.iter().map(|s| s.span_refs.len()).sum()
+    }
+}
+
+struct Entry {
+    due: Duration,
+    sequence: u64,
+    trace: OpenTrace,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.sequence).cmp(&(other.due, other.sequence))
+    }
+}
+
+/// Interleaves a bounded number of open traces on the output stream.
+///
+/// Every open trace is scheduled with the time at which its next batch is due. Due times are
+/// measured on a virtual timeline relative to the start of the run, so the order of emission does
+/// not depend on how fast spans are generated. Traces that are due at the same time are emitted in
+/// the order they were scheduled.
+///
+/// In real time mode, batches are also held back until they are due in real time, so that batch
+/// delays slow down the run. Otherwise, the virtual timeline advances without waiting.
+pub struct Scheduler {
+    capacity: usize,
+    queue: BinaryHeap<Reverse<Entry>>,
+    sequence: u64,
+    start: Instant,
+    now: Duration,
+    realtime: bool,
+}
+
+impl Scheduler {
+    pub fn new(capacity: usize, realtime: bool) -> Self {
+        Self {
+            capacity,
+            queue: BinaryHeap::with_capacity(capacity),
+            sequence: 0,
+            start: Instant::now(),
+            now: Duration::ZERO,
+            realtime,
+        }
+    }
+
+    /// Returns the current time on the virtual timeline.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Moves the virtual timeline forward to `now`, if it is ahead of the current time.
+    pub fn advance(&mut self, now: Duration) {
+        self.now = self.now.max(now);
+    }
+
+    /// Returns the number of open traces.
+    pub fn open_traces(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if another trace can be opened.
+    pub fn has_capacity(&self) -> bool {
+        self.queue.len() < self.capacity
+    }
+
+    /// Schedules the next batch of `trace` to be emitted at `due` on the virtual timeline.
+    pub fn schedule(&mut self, trace: OpenTrace, due: Duration) {
+        self.sequence += 1;
+        self.queue.push(Reverse(Entry {
+            due,
+            sequence: self.sequence,
+            trace,
+        }));
+    }
+
+    /// Removes the trace with the earliest due batch. In real time mode, this blocks until the batch
+    /// is due in real time.
+    ///
+    /// Returns `None` if there are no open traces.
+    pub fn pop(&mut self) -> Option<OpenTrace> {
+        let Reverse(entry) = self.queue.pop()?;
+        self.advance(entry.due);
+        if !self.realtime {
+            return Some(entry.trace);
+        }
+
+        let sleep_duration = (self.start + self.now).saturating_duration_since(Instant::now());
+        if !sleep_duration.is_zero() {
+            thread::sleep(sleep_duration);
+        }
+
+        Some(entry.trace)
+    }
+}
@@ -0,0 +1,74 @@
+//! Validation of messages against the vendored `snuba-spans` schema.
+
+use anyhow::Result;
+use jsonschema::Validator;
+use serde_json::Value;
+
+/// The JSON schema of messages on the `snuba-spans` topic.
+const SNUBA_SPANS_SCHEMA: &str = include_str!("../schemas/snuba-spans.v1.schema.json");
+
+/// Checks messages against the `snuba-spans` schema and counts violations.
+pub struct SchemaValidator {
+    validator: Validator,
+    invalid: usize,
+}
+
+impl SchemaValidator {
+    pub fn new() -> Result<Self> {
+        let schema = serde_json::from_str(SNUBA_SPANS_SCHEMA)?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| anyhow::anyhow!("invalid snuba-spans schema: {e}"))?;
+
+        Ok(Self {
+            validator,
+            invalid: 0,
+        })
+    }
+
+    /// Returns the number of invalid messages seen so far.
+    pub fn invalid(&self) -> usize {
+        self.invalid
+    }
+
+    /// Validates a message and returns all violations with the paths of the offending fields.
+    pub fn violations(&mut self, payload: &[u8]) -> Vec<String> {
+        let violations = match serde_json::from_slice::<Value>(payload) {
+            Ok(instance) => self
+                .validator
+                .iter_errors(&instance)
+                .map(|error| match error.instance_path.as_str() {
+                    "" => format!("/: {error}"),
+                    path => format!("{path}: {error}"),
+                })
+                .collect(),
+            Err(error) => vec![format!("invalid JSON: {error}")],
+        };
+
+        if !violations.is_empty() {
+            self.invalid += 1;
+        }
+
+        violations
+    }
+
+    /// Validates a message and logs its violations.
+    pub fn check(&mut self, payload: &[u8]) {
+        let violations = self.violations(payload);
+        if violations.is_empty() {
+            return;
+        }
+
+        // Only log the first invalid message, since all others are usually invalid for the same
+        // reason.
+        if self.invalid == 1 {
+            log::error!("message violates the snuba-spans schema:");
+            for violation in &violations {
+                log::error!("  {violation}");
+            }
+        } else {
+            for violation in &violations {
+                log::debug!("schema violation: {violation}");
+            }
+        }
+    }
+}
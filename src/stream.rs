@@ -0,0 +1,168 @@
+//! Generation of spans in the order they are emitted.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use time::OffsetDateTime;
+
+use crate::cli::Config;
+use crate::data::{RandomGenerator, Span, Stats};
+use crate::manifest::TraceManifest;
+use crate::producer::MessageKey;
+use crate::scheduler::{OpenTrace, Scheduler};
+use crate::throttle::Throttle;
+
+/// Spans of a single segment that are flushed together.
+#[derive(Debug)]
+pub struct Batch {
+    pub key: MessageKey,
+    pub spans: Vec<Span>,
+    /// The time at which each span is due to be emitted, or empty if throughput is not limited.
+    pub due: Vec<Instant>,
+}
+
+/// Generates traces and yields their spans in batches, in the order they are emitted.
+///
+/// Batches are interleaved across concurrent traces, and every span is assigned the time at which
+/// it is due at the configured throughput. The consumer is responsible for waiting until then, see
+/// [`sleep_until`](crate::throttle::sleep_until). No new
+/// traces are opened once `count` spans have been scheduled or `duration` has elapsed, and the
+/// stream ends when all open traces are emitted.
+///
+/// Without a throughput limit, the timeline only advances with batch delays. New traces are then
+/// spread out as if `concurrent_traces` traces were in flight at all times: every trace starts
+/// after the previous one by its root duration divided by the number of concurrent traces.
+///
+/// Batch delays only hold back batches in real time if the throughput is limited or `duration` is
+/// set. Otherwise, the stream yields batches as fast as they are consumed, and batch delays only
+/// show in the timestamps of the spans.
+pub struct SpanStream<'a> {
+    config: &'a Config,
+    generator: RandomGenerator<'a>,
+    throttle: Throttle,
+    scheduler: Scheduler,
+    start_time: OffsetDateTime,
+    deadline: Option<Instant>,
+    scheduled_spans: usize,
+    /// The offset of new traces on the timeline while the throughput is not limited.
+    arrival_offset: Duration,
+    manifest: Vec<TraceManifest>,
+}
+
+impl<'a> SpanStream<'a> {
+    pub fn new(config: &'a Config) -> Result<Self> {
+        Ok(Self {
+            config,
+            generator: RandomGenerator::new(config)?,
+            throttle: Throttle::new(config),
+            scheduler: Scheduler::new(
+                config.concurrent_traces,
+                config.rate_schedule().is_some() || config.duration.is_some(),
+            ),
+            start_time: OffsetDateTime::from_unix_timestamp(config.start_time)?,
+            deadline: config.duration.map(|duration| Instant::now() + duration.0),
+            scheduled_spans: 0,
+            arrival_offset: Duration::ZERO,
+            manifest: Vec::new(),
+        })
+    }
+
+    /// Returns the number of spans, segments and traces emitted so far.
+    pub fn stats(&self) -> &Stats {
+        self.generator.stats()
+    }
+
+    /// Returns the number of traces that have been started but not fully emitted.
+    pub fn open_traces(&self) -> usize {
+        self.scheduler.open_traces()
+    }
+
+    /// Returns the manifest entries of traces opened since the last call, if `manifest` is set in
+    /// the config.
+    pub fn take_manifest(&mut self) -> Vec<TraceManifest> {
+        std::mem::take(&mut self.manifest)
+    }
+
+    /// Returns the average rate in spans per second that the throughput schedule allowed so far.
+    pub fn target_rate(&self) -> f64 {
+        self.throttle.target_rate()
+    }
+}
+
+impl Iterator for SpanStream<'_> {
+    type Item = Batch;
+
+    fn next(&mut self) -> Option<Batch> {
+        let generator = &mut self.generator;
+        let scheduler = &mut self.scheduler;
+
+        loop {
+            // All timestamps are derived from the virtual timeline of the scheduler to make runs
+            // reproducible with the same seed.
+            while scheduler.has_capacity()
+                && self
+                    .config
+                    .count
+                    .is_none_or(|count| self.scheduled_spans < count)
+                && self
+                    .deadline
+                    .is_none_or(|deadline| Instant::now() < deadline)
+            {
+                let timestamp = self.start_time + scheduler.now() + self.arrival_offset;
+                let trace = OpenTrace::generate(generator, timestamp);
+                self.scheduled_spans += trace.span_count();
+                if !self.throttle.is_limited() {
+                    self.arrival_offset += trace.duration() / self.config.concurrent_traces as u32;
+                }
+                if self.config.manifest.is_some() {
+                    self.manifest.push(TraceManifest::new(&trace));
+                }
+                scheduler.schedule(trace, scheduler.now());
+            }
+
+            let mut trace = scheduler.pop()?;
+            let Some(segment) = trace.segments.front_mut() else {
+                continue;
+            };
+
+            let batch = segment.next_batch(self.config.batch_size);
+            let key = MessageKey {
+                trace_id: segment.info.trace.trace_id,
+                project_id: segment.info.project_id,
+            };
+            let received = generator.received(&segment.info, segment.flush_delay);
+
+            let mut spans = Vec::with_capacity(batch.len());
+            let mut due = Vec::new();
+            for span_ref in &segment.span_refs[batch] {
+                let mut span = generator.span(&segment.info, *span_ref, received);
+                if span_ref.parent_id.is_none() {
+                    debug_assert!(span_ref.span_id == segment.info.span_id);
+                    span.parent_span_id = segment.remote_parent;
+                    span.is_remote = segment.remote_parent.is_some();
+                }
+
+                generator.pad(&mut span);
+                spans.push(span);
+                due.extend(self.throttle.accept());
+            }
+
+            scheduler.advance(self.throttle.elapsed());
+
+            // SDKs flush spans in batches, so the remainder of a segment is delayed. The next
+            // segment of the trace starts right away.
+            if !segment.is_done() {
+                let delay = generator.batch_delay();
+                segment.flush_delay += delay;
+                scheduler.schedule(trace, scheduler.now() + delay);
+            } else {
+                trace.segments.pop_front();
+                if !trace.segments.is_empty() {
+                    scheduler.schedule(trace, scheduler.now());
+                }
+            }
+
+            return Some(Batch { key, spans, due });
+        }
+    }
+}
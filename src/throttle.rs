@@ -0,0 +1,186 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Exp1};
+
+use crate::cli::{Config, RateSchedule};
+
+/// The remaining time below which [`sleep_until`] spins instead of sleeping, since sleeps overshoot
+/// by up to a scheduler tick.
+const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+
+/// The lowest rate in spans per second, so that a schedule at zero does not stall the run forever.
+const MIN_RATE: f64 = 0.01;
+
+/// The largest delay behind the schedule that is made up by emitting spans right away. After a
+/// longer stall, spans are due from the current time on instead of in a burst.
+const MAX_BACKLOG: Duration = Duration::from_secs(1);
+
+/// The resolution at which the throughput schedule is integrated at low rates.
+const INTEGRATION_STEP: Duration = Duration::from_millis(10);
+
+/// Mixed into the seed for jitter, so that it does not repeat the random stream of the generator.
+const JITTER_SEED: u64 = 0x6a09_e667_f3bc_c908;
+
+/// Assigns every span the time at which it is due according to the throughput schedule.
+///
+/// This works like a token bucket that refills at the scheduled rate and holds up to
+/// [`MAX_BACKLOG`] worth of spans: spans that fall behind their due time are emitted right away, so
+/// the average rate matches the schedule after short delays. Longer stalls are not made up, so the
+/// rate never exceeds the schedule by more than the capacity of the bucket. With jitter, the
+/// intervals between spans are exponentially distributed, as in a Poisson process.
+///
+/// The schedule itself runs on a virtual timeline that only depends on the accepted spans, so a
+/// slow sink delays spans in real time but never changes the generated data.
+pub struct Throttle {
+    schedule: Option<RateSchedule>,
+    jitter: Option<StdRng>,
+    /// The time on the schedule at which the last accepted span is due.
+    due: Duration,
+    accepted: usize,
+    start: Instant,
+    /// The real time by which the schedule has been pushed back after stalls.
+    lag: Duration,
+}
+
+impl Throttle {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            schedule: config.rate_schedule(),
+            jitter: config
+                .throughput_jitter
+                .then(|| StdRng::seed_from_u64(config.seed ^ JITTER_SEED)),
+            due: Duration::ZERO,
+            accepted: 0,
+            start: Instant::now(),
+            lag: Duration::ZERO,
+        }
+    }
+
+    /// Accepts the next span and returns the time at which it is due, or `None` if the throughput
+    /// is not limited.
+    pub fn accept(&mut self) -> Option<Instant> {
+        let schedule = self.schedule.as_ref()?;
+
+        // The number of spans that the schedule has to emit until the next span is due. Integrate
+        // the rate in small steps while spans are far apart, so that the next span is placed
+        // accurately when the rate changes quickly or is close to zero.
+        let mut spans: f64 = match self.jitter {
+            Some(ref mut rng) => Exp1.sample(rng),
+            None => 1.0,
+        };
+
+        let step = INTEGRATION_STEP.as_secs_f64();
+        loop {
+            let rate = schedule.rate(self.due).max(MIN_RATE);
+            let interval = spans / rate;
+            if interval <= step {
+                self.due += Duration::from_secs_f64(interval);
+                break;
+            }
+
+            spans -= rate * step;
+            self.due += INTEGRATION_STEP;
+        }
+
+        self.accepted += 1;
+
+        // Drop the part of the backlog that exceeds the capacity of the bucket by pushing back the
+        // real time at which the schedule runs.
+        let due = self.start + self.lag + self.due;
+        if let Some(earliest) = Instant::now().checked_sub(MAX_BACKLOG)
+            && due < earliest
+        {
+            self.lag += earliest - due;
+        }
+
+        Some(self.start + self.lag + self.due)
+    }
+
+    /// Returns `true` if the throughput is limited by a schedule.
+    pub fn is_limited(&self) -> bool {
+        self.schedule.is_some()
+    }
+
+    /// Returns the time it takes to emit all accepted spans at the configured throughput, regardless
+    /// of stalls.
+    pub fn elapsed(&self) -> Duration {
+        self.due
+    }
+
+    /// Returns the average rate in spans per second that the schedule allowed for all accepted
+    /// spans, or `0` if the throughput is not limited.
+    pub fn target_rate(&self) -> f64 {
+        if self.due.is_zero() {
+            return 0.0;
+        }
+
+        self.accepted as f64 / self.due.as_secs_f64()
+    }
+}
+
+/// Blocks until `deadline` with sub-millisecond accuracy.
+pub fn sleep_until(deadline: Instant) {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            thread::yield_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backlog_is_capped() {
+        let config = Config {
+            throughput: Some(100),
+            ..Config::default()
+        };
+
+        let mut throttle = Throttle::new(&config);
+        throttle.start -= Duration::from_secs(10);
+
+        // After a stall of 10 seconds, only one second worth of spans is due right away.
+        let now = Instant::now();
+        let overdue = (0..1000)
+            .filter_map(|_| throttle.accept())
+            .filter(|&due| due <= now)
+            .count();
+        assert!((99..=101).contains(&overdue), "{overdue} spans overdue");
+    }
+
+    #[test]
+    fn test_stall_keeps_timeline() {
+        let config = Config {
+            throughput: Some(100),
+            throughput_jitter: true,
+            ..Config::default()
+        };
+
+        let timeline = |stall: bool| {
+            let mut throttle = Throttle::new(&config);
+            (0..1000)
+                .map(|index| {
+                    if stall && index == 500 {
+                        throttle.start -= Duration::from_secs(10);
+                    }
+                    throttle.accept();
+                    throttle.elapsed()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(timeline(false), timeline(true));
+    }
+}
@@ -4,6 +4,8 @@
 
 use std::{fmt, str};
 
+use rand::Rng;
+use rand::distr::{Distribution, StandardUniform};
 use serde::{Deserialize, Serialize};
 
 /// Holds the identifier for a Span
@@ -17,6 +19,24 @@ impl Default for SpanId {
     }
 }
 
+impl SpanId {
+    /// Creates a span ID from its raw bytes.
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the raw bytes of the span ID.
+    pub fn as_bytes(&self) -> &[u8; 8] {
+        &self.0
+    }
+}
+
+impl Distribution<SpanId> for StandardUniform {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> SpanId {
+        SpanId(rng.random())
+    }
+}
+
 impl fmt::Display for SpanId {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "{}", hex::encode(self.0))
@@ -58,6 +78,24 @@ impl Default for TraceId {
     }
 }
 
+impl TraceId {
+    /// Creates a trace ID from its raw bytes.
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the raw bytes of the trace ID.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl Distribution<TraceId> for StandardUniform {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TraceId {
+        TraceId(rng.random())
+    }
+}
+
 impl fmt::Display for TraceId {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "{}", hex::encode(self.0))